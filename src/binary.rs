@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+
+/// How many leading bytes are inspected for NUL bytes
+const SNIFF_LEN: usize = 8192;
+
+/// How many leading bytes are shown in a hex summary
+const HEX_SUMMARY_LEN: usize = 256;
+
+/// Signatures of common binary formats, caught even when the sampled bytes look like text.
+/// Signatures made only of printable ASCII are left out since text files can start with them.
+const MAGIC_NUMBERS: &[&[u8]] = &[
+    b"\x89PNG\r\n\x1a\n",   // PNG
+    b"\xff\xd8\xff",        // JPEG
+    b"PK\x03\x04",          // ZIP, JAR, DOCX, ...
+    b"\x1f\x8b",            // gzip
+    b"\xfd7zXZ\x00",        // xz
+    b"7z\xbc\xaf\x27\x1c",  // 7-Zip
+    b"\x28\xb5\x2f\xfd",    // zstd
+    b"\x7fELF",             // ELF
+    b"\xcf\xfa\xed\xfe",    // Mach-O 64-bit
+    b"\xce\xfa\xed\xfe",    // Mach-O 32-bit
+    b"\xca\xfe\xba\xbe",    // Mach-O fat binary, Java class
+    b"\x00asm",             // WebAssembly
+    b"SQLite format 3\x00", // SQLite
+];
+
+/// What to do with files that are detected as binary
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum BinaryMode {
+    /// List binary files in the tree but leave out their content
+    #[default]
+    Skip,
+    /// Include content decoded as UTF-8 with invalid bytes replaced
+    Lossy,
+    /// Include a hex dump of the first bytes
+    Hex,
+}
+
+/// Classifies file contents as binary by magic number, NUL bytes or invalid UTF-8
+pub fn is_binary(bytes: &[u8]) -> bool {
+    if MAGIC_NUMBERS.iter().any(|magic| bytes.starts_with(magic)) {
+        return true;
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+
+    std::str::from_utf8(bytes).is_err()
+}
+
+/// Summarizes binary contents as an `xxd`-style dump of the leading bytes
+pub fn hex_summary(bytes: &[u8]) -> String {
+    let shown = &bytes[..bytes.len().min(HEX_SUMMARY_LEN)];
+    let mut out = String::new();
+
+    for (index, chunk) in shown.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{:08x}: {:<47}  {}\n",
+            index * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+
+    if bytes.len() > shown.len() {
+        out.push_str(&format!(
+            "[... {} more bytes ...]\n",
+            bytes.len() - shown.len()
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_magic_numbers() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\nrest"));
+        assert!(is_binary(b"\x7fELF"));
+        assert!(is_binary(b"SQLite format 3\x00"));
+    }
+
+    #[test]
+    fn text_starting_like_a_signature_is_text() {
+        assert!(!is_binary(b"MZ is a prefix in this note\n"));
+        assert!(!is_binary(
+            b"ID3 tags are stored at the start of MP3 files\n"
+        ));
+        assert!(!is_binary(b"RIFF chunks are described below\n"));
+        assert!(!is_binary(b"%PDF-1.4 minimal example written as text\n"));
+    }
+
+    #[test]
+    fn detects_nul_bytes_and_invalid_utf8() {
+        assert!(is_binary(b"text\x00more"));
+        assert!(is_binary(b"caf\xe9"));
+        assert!(!is_binary("caf\u{e9} \u{2713}\n".as_bytes()));
+        assert!(!is_binary(b""));
+    }
+
+    #[test]
+    fn hex_summary_formats_rows() {
+        let summary = hex_summary(b"Hello, world!\n\x00\xffAB");
+
+        assert_eq!(
+            summary,
+            "00000000: 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 ff  Hello, world!...\n\
+             00000010: 41 42                                            AB\n"
+        );
+    }
+
+    #[test]
+    fn hex_summary_truncates_long_input() {
+        let summary = hex_summary(&[0u8; 1000]);
+
+        assert_eq!(summary.lines().count(), HEX_SUMMARY_LEN / 16 + 1);
+        assert!(summary.ends_with("\n[... 744 more bytes ...]\n"));
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    format::Format,
+    secrets::SecretsConfig,
+    sink::ClipboardChoice,
+    tokens::{BudgetPolicy, Tokenizer},
+};
+
+/// Name of the project config file, looked up from the scanned directory upwards
+const PROJECT_CONFIG: &str = "prelude.toml";
+
+/// Defaults read from `prelude.toml` and the user config, overridden by command line flags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Directories or files to scan, like `-P`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<PathBuf>,
+    /// Patterns a file must match to be included, like `-M`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<Tokenizer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_policy: Option<BudgetPolicy>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub priority: Vec<String>,
+    /// File the prompt is written to, or `-` for stdout, like `-F`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<ClipboardChoice>,
+    #[serde(skip_serializing_if = "SecretsConfig::is_empty")]
+    pub secrets: SecretsConfig,
+    /// Named sets of settings applied on top of the rest with `--profile`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, Config>,
+}
+
+/// Location of the user config, e.g. `~/.config/rustprelude/config.toml`
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustprelude").join("config.toml"))
+}
+
+/// Finds the nearest `prelude.toml` at or above `start`, stopping at the git root
+///
+/// Relative starts are taken from the current directory.
+pub fn project_config_path(start: &Path) -> Option<PathBuf> {
+    let start = env::current_dir().ok()?.join(start);
+    for dir in start.ancestors() {
+        let candidate = dir.join(PROJECT_CONFIG);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+impl Config {
+    /// Reads a config file, resolving relative paths against the file's directory
+    fn read(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+
+        for (name, profile) in &config.profile {
+            if !profile.profile.is_empty() {
+                bail!(
+                    "Invalid config {}: profile '{}' cannot contain other profiles",
+                    path.display(),
+                    name
+                );
+            }
+        }
+
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    /// Makes the scanned paths, output file and template files relative to `dir` instead of the working directory
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in &mut self.paths {
+            *path = dir.join(&*path);
+        }
+        if let Some(output) = self
+            .output
+            .as_mut()
+            .filter(|output| *output != Path::new("-"))
+        {
+            *output = dir.join(&*output);
+        }
+        for template in [&mut self.template, &mut self.file_template]
+            .into_iter()
+            .flatten()
+        {
+            let relative = dir.join(&*template);
+            if relative.is_file() {
+                *template = relative.display().to_string();
+            }
+        }
+        for profile in self.profile.values_mut() {
+            profile.resolve_paths(dir);
+        }
+    }
+
+    /// Loads the user config and then the project config, with the project taking precedence
+    ///
+    /// The project config is searched for from `start`, the first scanned path.
+    /// Returns the merged config along with the files it was read from.
+    pub fn load(start: &Path) -> Result<(Config, Vec<PathBuf>)> {
+        let mut config = Config::default();
+        let mut sources = Vec::new();
+
+        for path in [user_config_path(), project_config_path(start)]
+            .into_iter()
+            .flatten()
+            .filter(|path| path.is_file())
+        {
+            info!("Loading config from {}", path.display());
+            config = config.merge(Config::read(&path)?);
+            sources.push(path);
+        }
+        Ok((config, sources))
+    }
+
+    /// Layers `over` on top of this config; every setting given in `over` wins
+    pub fn merge(self, over: Config) -> Config {
+        fn list<T>(base: Vec<T>, over: Vec<T>) -> Vec<T> {
+            if over.is_empty() {
+                base
+            } else {
+                over
+            }
+        }
+
+        let mut profile = self.profile;
+        for (name, over) in over.profile {
+            let merged = match profile.remove(&name) {
+                Some(base) => base.merge(over),
+                None => over,
+            };
+            profile.insert(name, merged);
+        }
+
+        Config {
+            paths: list(self.paths, over.paths),
+            include: list(self.include, over.include),
+            exclude: list(self.exclude, over.exclude),
+            format: over.format.or(self.format),
+            template: over.template.or(self.template),
+            file_template: over.file_template.or(self.file_template),
+            task: over.task.or(self.task),
+            max_tokens: over.max_tokens.or(self.max_tokens),
+            tokenizer: over.tokenizer.or(self.tokenizer),
+            budget_policy: over.budget_policy.or(self.budget_policy),
+            priority: list(self.priority, over.priority),
+            // Asking for a clipboard backend also overrides an output file set further down
+            output: over.output.or(if over.clipboard.is_some() {
+                None
+            } else {
+                self.output
+            }),
+            clipboard: over.clipboard.or(self.clipboard),
+            secrets: self.secrets.merge(over.secrets),
+            profile,
+        }
+    }
+
+    /// Applies the named profile on top of the top-level settings
+    pub fn select(mut self, name: &str) -> Result<Config> {
+        let Some(profile) = self.profile.remove(name) else {
+            let available: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            bail!(
+                "Unknown profile '{}' (available: {})",
+                name,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            );
+        };
+
+        self.profile.clear();
+        Ok(self.merge(profile))
+    }
+
+    /// Renders the config as TOML, as accepted in `prelude.toml`
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn merge_prefers_the_upper_layer() {
+        let base = parse(
+            r#"
+            include = ["*.rs"]
+            exclude = ["target/*"]
+            task = "base"
+            max-tokens = 1000
+            "#,
+        );
+        let over = parse(
+            r#"
+            include = ["*.py"]
+            task = "over"
+            "#,
+        );
+
+        let merged = base.merge(over);
+        assert_eq!(merged.include, ["*.py"]);
+        assert_eq!(merged.exclude, ["target/*"]);
+        assert_eq!(merged.task.as_deref(), Some("over"));
+        assert_eq!(merged.max_tokens, Some(1000));
+    }
+
+    #[test]
+    fn clipboard_overrides_inherited_output() {
+        let base = parse(r#"output = "prompt.txt""#);
+        let merged = base.merge(parse(r#"clipboard = "osc52""#));
+        assert_eq!(merged.output, None);
+        assert_eq!(merged.clipboard, Some(ClipboardChoice::Osc52));
+
+        let base = parse(r#"clipboard = "osc52""#);
+        let merged = base.merge(parse(r#"output = "prompt.txt""#));
+        assert_eq!(merged.output, Some(PathBuf::from("prompt.txt")));
+    }
+
+    #[test]
+    fn profiles_merge_by_name() {
+        let base = parse(
+            r#"
+            [profile.review]
+            task = "review"
+            max-tokens = 1000
+            "#,
+        );
+        let over = parse(
+            r#"
+            [profile.review]
+            max-tokens = 2000
+            [profile.docs]
+            include = ["*.md"]
+            "#,
+        );
+
+        let merged = base.merge(over);
+        let review = &merged.profile["review"];
+        assert_eq!(review.task.as_deref(), Some("review"));
+        assert_eq!(review.max_tokens, Some(2000));
+        assert!(merged.profile.contains_key("docs"));
+    }
+
+    #[test]
+    fn select_applies_the_profile() {
+        let config = parse(
+            r#"
+            include = ["*.rs"]
+            task = "base"
+            [profile.review]
+            task = "review"
+            "#,
+        );
+
+        let selected = config.select("review").unwrap();
+        assert_eq!(selected.include, ["*.rs"]);
+        assert_eq!(selected.task.as_deref(), Some("review"));
+        assert!(selected.profile.is_empty());
+    }
+
+    #[test]
+    fn select_lists_available_profiles() {
+        let config = parse(
+            r#"
+            [profile.review]
+            [profile.docs]
+            "#,
+        );
+        let err = config.select("missing").unwrap_err().to_string();
+        assert_eq!(err, "Unknown profile 'missing' (available: docs, review)");
+
+        let err = Config::default().select("missing").unwrap_err().to_string();
+        assert!(err.ends_with("(available: none)"), "{}", err);
+    }
+
+    #[test]
+    fn resolves_paths_against_the_config_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("prompt.tmpl"), "{tree}").unwrap();
+        let path = dir.path().join(PROJECT_CONFIG);
+        fs::write(
+            &path,
+            r#"
+            paths = ["src"]
+            template = "prompt.tmpl"
+            file-template = "missing.tmpl"
+            output = "out/prompt.md"
+            [profile.stdout]
+            output = "-"
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::read(&path).unwrap();
+        assert_eq!(config.paths, [dir.path().join("src")]);
+        assert_eq!(
+            config.template,
+            Some(dir.path().join("prompt.tmpl").display().to_string())
+        );
+        assert_eq!(config.file_template.as_deref(), Some("missing.tmpl"));
+        assert_eq!(config.output, Some(dir.path().join("out/prompt.md")));
+        assert_eq!(config.profile["stdout"].output, Some(PathBuf::from("-")));
+    }
+
+    #[test]
+    fn project_config_is_found_from_the_scanned_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+        fs::write(dir.path().join(PROJECT_CONFIG), "").unwrap();
+
+        assert_eq!(
+            project_config_path(&dir.path().join("sub/deeper")),
+            Some(dir.path().join(PROJECT_CONFIG))
+        );
+
+        // The search stops at the git root
+        let nested = dir.path().join("nested");
+        fs::create_dir_all(nested.join(".git")).unwrap();
+        assert_eq!(project_config_path(&nested), None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("includes = [\"*.rs\"]").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The instruction placed at the top of the prompt when no `--task` is given
+pub const DEFAULT_TASK: &str = "I want you to help me fix some issues with my code.";
+
+/// The layout used to render the prompt
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// `--- File: path ---` separators
+    #[default]
+    Plain,
+    /// Fenced code blocks tagged with the file's language
+    Markdown,
+    /// `<file path="...">` elements
+    Xml,
+    /// A structured `{tree, files}` document
+    Json,
+}
+
+/// A file ready to be rendered into the prompt
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub size: usize,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Estimated tokens in `content`
+    #[serde(skip)]
+    pub tokens: usize,
+    /// Size of the file on disk, before redaction or reshaping
+    #[serde(skip)]
+    pub file_size: u64,
+}
+
+impl OutputFile {
+    pub fn new(path: PathBuf, content: String) -> Self {
+        OutputFile {
+            path,
+            size: content.len(),
+            content,
+            diff: None,
+            tokens: 0,
+            file_size: 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonPrompt<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task: Option<&'a str>,
+    tree: &'a str,
+    files: &'a [OutputFile],
+}
+
+/// Guesses a code fence language tag from a file's extension or name
+pub fn language_for(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "dockerfile" => return "dockerfile",
+        "makefile" | "gnumakefile" => return "makefile",
+        "cmakelists.txt" => return "cmake",
+        _ => {}
+    }
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "sh" | "bash" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "md" | "markdown" => "markdown",
+        "proto" => "protobuf",
+        "nix" => "nix",
+        "zig" => "zig",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "scala" => "scala",
+        "dart" => "dart",
+        "r" => "r",
+        _ => "",
+    }
+}
+
+/// Prefixes every line with its right-aligned line number
+pub fn number_lines(content: &str, separator: &str) -> String {
+    let width = content.lines().count().max(1).to_string().len();
+    let mut out = String::with_capacity(content.len() + content.len() / 4);
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        out.push_str(&format!("{:>width$}{}{}", index + 1, separator, line));
+    }
+    out
+}
+
+/// Picks a backtick fence longer than any backtick run inside `content`
+fn fence_for(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Format {
+    /// Renders a single file, including its diff when present
+    pub fn render_file(self, file: &OutputFile) -> String {
+        let path = file.path.display().to_string();
+        match self {
+            Format::Plain => {
+                let mut out = format!("\n\n--- File: {} ---\n\n{}", path, file.content);
+                if let Some(ref diff) = file.diff {
+                    out.push_str(&format!("\n\n--- Diff: {} ---\n\n{}", path, diff));
+                }
+                out
+            }
+            Format::Markdown => {
+                let fence = fence_for(&file.content);
+                let mut out = format!(
+                    "\n\n## File: {}\n\n{}{}\n{}\n{}",
+                    path,
+                    fence,
+                    language_for(&file.path),
+                    file.content.trim_end_matches('\n'),
+                    fence
+                );
+                if let Some(ref diff) = file.diff {
+                    let fence = fence_for(diff);
+                    out.push_str(&format!(
+                        "\n\n### Diff: {}\n\n{}diff\n{}\n{}",
+                        path,
+                        fence,
+                        diff.trim_end_matches('\n'),
+                        fence
+                    ));
+                }
+                out
+            }
+            Format::Xml => {
+                let path = escape_attribute(&path);
+                let mut out = format!(
+                    "<file path=\"{}\">\n{}\n</file>\n",
+                    path,
+                    file.content.trim_end_matches('\n')
+                );
+                if let Some(ref diff) = file.diff {
+                    out.push_str(&format!(
+                        "<diff path=\"{}\">\n{}\n</diff>\n",
+                        path,
+                        diff.trim_end_matches('\n')
+                    ));
+                }
+                out
+            }
+            Format::Json => serde_json::to_string(file).unwrap_or_default(),
+        }
+    }
+
+    /// The prompt template used when no `--template` is given
+    ///
+    /// JSON output is a fixed document and has no template.
+    pub fn default_template(self) -> Option<&'static str> {
+        match self {
+            Format::Plain => Some(
+                "{task}\n
+I have attached the code and file structure.\n\n
+File Tree:\n{tree}\n
+Concatenated Files:{files}",
+            ),
+            Format::Markdown => Some(
+                "{task}\n
+I have attached the code and file structure.\n
+## File Tree\n\n```\n{tree}```\n\n## Files{files}\n",
+            ),
+            Format::Xml => Some(
+                "{task}\n
+I have attached the code and file structure.\n
+<file_tree>\n{tree}</file_tree>\n\n<files>\n{files}</files>\n",
+            ),
+            Format::Json => None,
+        }
+    }
+
+    /// Renders the structured JSON document
+    pub fn render_json(tree: &str, task: Option<&str>, files: &[OutputFile]) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&JsonPrompt {
+            task,
+            tree,
+            files,
+        })?)
+    }
+}
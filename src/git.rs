@@ -0,0 +1,86 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs a git command inside `root` and returns its stdout
+fn run_git(root: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .context("Failed to run git - is it installed and on PATH?")?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+/// Splits NUL-separated git output into paths
+fn split_paths(stdout: &[u8]) -> HashSet<PathBuf> {
+    stdout
+        .split(|&b| b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| PathBuf::from(String::from_utf8_lossy(p).into_owned()))
+        .collect()
+}
+
+/// Lists the files in the git index below `root`, relative to `root`
+pub fn tracked_files(root: &Path) -> Result<HashSet<PathBuf>> {
+    let stdout = run_git(root, &["ls-files", "-z", "--cached"])?;
+    Ok(split_paths(&stdout))
+}
+
+/// The set of changes to compare the working tree against
+#[derive(Debug, Clone)]
+pub enum DiffTarget {
+    /// Working tree compared to an arbitrary ref
+    Since(String),
+    /// Index compared to HEAD
+    Staged,
+    /// Working tree compared to the index
+    Working,
+}
+
+impl DiffTarget {
+    fn diff_args(&self) -> Vec<&str> {
+        match self {
+            DiffTarget::Since(reference) => vec![reference.as_str()],
+            DiffTarget::Staged => vec!["--cached"],
+            DiffTarget::Working => vec![],
+        }
+    }
+}
+
+/// Lists files changed for `target` below `root`, relative to `root`
+pub fn changed_files(root: &Path, target: &DiffTarget) -> Result<HashSet<PathBuf>> {
+    let mut args = vec!["diff", "--relative", "--name-only", "-z"];
+    args.extend(target.diff_args());
+    let stdout = run_git(root, &args)?;
+    Ok(split_paths(&stdout))
+}
+
+/// Produces the unified diff of a single file for `target`
+pub fn file_diff(root: &Path, target: &DiffTarget, path: &Path) -> Result<String> {
+    let path = path.to_string_lossy();
+    let mut args = vec!["diff", "--relative", "--no-color"];
+    args.extend(target.diff_args());
+    args.extend(["--", path.as_ref()]);
+    let stdout = run_git(root, &args)?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Returns the name of the branch checked out in `root`
+pub fn current_branch(root: &Path) -> Result<String> {
+    let stdout = run_git(root, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    Ok(String::from_utf8_lossy(&stdout).trim().to_string())
+}
@@ -0,0 +1,62 @@
+//! Packs the files of a codebase, along with a tree of its layout, into a single prompt
+//!
+//! The [`Packer`] builder walks one or more roots, applies ignore files and filters,
+//! and renders the files through a [`format::Format`] or template into a [`Pack`].
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+pub mod binary;
+pub mod config;
+pub mod format;
+pub mod git;
+pub mod outline;
+mod pack;
+pub mod rules;
+pub mod secrets;
+pub mod sink;
+pub mod skeleton;
+pub mod strip;
+pub mod template;
+pub mod tokens;
+pub mod tree;
+
+pub use pack::{Pack, Packer, SkipReason, SkippedFile, Stats};
+
+/// Name of the ignore files read in every directory alongside .gitignore
+pub const PRELUDE_IGNORE: &str = ".preludeignore";
+
+/// Compiles glob patterns into a set, or `None` when no patterns were given
+fn build_globset(patterns: &[String], case_sensitive: bool) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .with_context(|| format!("Invalid pattern: {}", pattern))?;
+        builder.add(glob);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Checks a relative path against a glob set, trying both the full path and the bare filename
+fn matches_globset(set: &GlobSet, path: &Path) -> bool {
+    set.is_match(path) || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+/// Finds the first pattern in a glob set built from `patterns` that matches a relative path
+fn matching_pattern<'a>(set: &GlobSet, patterns: &'a [String], path: &Path) -> Option<&'a str> {
+    let mut indices = set.matches(path);
+    if let Some(name) = path.file_name() {
+        indices.extend(set.matches(name));
+    }
+    indices
+        .into_iter()
+        .min()
+        .map(|index| patterns[index].as_str())
+}
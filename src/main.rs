@@ -1,27 +1,50 @@
-use anyhow::{Context, Result};
-use clap::{command, Parser};
-use clipboard::{ClipboardContext, ClipboardProvider};
-use ignore::WalkBuilder;
-use log::{debug, error, info};
-use std::{
-    fs,
-    path::{Path, PathBuf},
+use anyhow::Result;
+use clap::Parser;
+use log::info;
+use rustprelude::{
+    binary::BinaryMode,
+    config::Config,
+    format::Format,
+    git::DiffTarget,
+    secrets::{SecretMode, SecretsConfig},
+    sink::{ClipboardChoice, Sink},
+    template,
+    tokens::{BudgetPolicy, Tokenizer},
+    tree::TreeStyle,
+    Packer, SkippedFile,
 };
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Specify a relative path to include only files below that path
-    #[arg(short = 'P')]
-    path: Option<PathBuf>,
+    /// Directories or files to scan (repeatable); defaults to the current directory
+    #[arg(short = 'P', value_name = "PATH")]
+    path: Vec<PathBuf>,
 
-    /// Specify a filename to save the prompt
+    /// Directories or files to scan, like -P
+    #[arg(value_name = "PATH")]
+    paths: Vec<PathBuf>,
+
+    /// Specify a filename to save the prompt, or `-` for stdout
     #[arg(short = 'F')]
     output_file: Option<PathBuf>,
 
-    /// Specify pattern(s) to match filenames
-    #[arg(short = 'M')]
-    match_pattern: Option<String>,
+    /// Write the prompt to stdout instead of the clipboard
+    #[arg(long, conflicts_with = "output_file")]
+    stdout: bool,
+
+    /// Clipboard backend: auto, x11, wayland, xclip, xsel, tmux, osc52 or file:PATH
+    #[arg(long, value_name = "BACKEND")]
+    clipboard: Option<ClipboardChoice>,
+
+    /// Specify pattern(s) to match filenames (comma-separated or repeated)
+    #[arg(short = 'M', value_delimiter = ',')]
+    match_pattern: Vec<String>,
+
+    /// Specify pattern(s) to exclude filenames (comma-separated or repeated)
+    #[arg(long = "exclude", value_delimiter = ',')]
+    exclude: Vec<String>,
 
     /// Only include files tracked by git
     #[arg(short = 'g')]
@@ -30,162 +53,338 @@ struct Args {
     /// Respect case sensitivity in pattern matching
     #[arg(short = 'c')]
     case_sensitive: bool,
-}
 
-fn build_tree(entries: &[PathBuf]) -> String {
-    let mut tree = String::from(".\n");
-    for entry in entries {
-        if let Some(path) = entry.to_str() {
-            tree.push_str(&format!("├── {}\n", path));
-        }
-    }
-    tree
+    /// Only include files changed between the working tree and a git ref
+    #[arg(long, value_name = "REF", group = "changes")]
+    since: Option<String>,
+
+    /// Only include files with staged changes
+    #[arg(long, group = "changes")]
+    staged: bool,
+
+    /// Only include files with unstaged changes in the working tree
+    #[arg(long, group = "changes")]
+    working: bool,
+
+    /// Append each file's unified diff after its content
+    #[arg(long, requires = "changes")]
+    diff: bool,
+
+    /// Show file sizes in the file tree
+    #[arg(long)]
+    tree_sizes: bool,
+
+    /// Show line counts in the file tree
+    #[arg(long)]
+    tree_lines: bool,
+
+    /// Draw the file tree with ASCII characters only
+    #[arg(long)]
+    ascii: bool,
+
+    /// Maximum number of tokens allowed in the prompt
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<usize>,
+
+    /// Tokenizer used to estimate token counts [default: heuristic]
+    #[arg(long, value_enum)]
+    tokenizer: Option<Tokenizer>,
+
+    /// How files are cut when the prompt exceeds --max-tokens [default: drop-largest]
+    #[arg(long, value_enum)]
+    budget_policy: Option<BudgetPolicy>,
+
+    /// Pattern(s) for files to cut last when enforcing --max-tokens
+    #[arg(long, value_delimiter = ',')]
+    priority: Vec<String>,
+
+    /// Output format of the prompt [default: plain]
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Prompt template file, or the name of a template in the config directory
+    #[arg(long, value_name = "TEMPLATE")]
+    template: Option<String>,
+
+    /// Template used to render each file, or the name of a template in the config directory
+    #[arg(long, value_name = "TEMPLATE")]
+    file_template: Option<String>,
+
+    /// Instruction placed at the top of the prompt
+    #[arg(long)]
+    task: Option<String>,
+
+    /// Reduce Rust files to signatures, replacing function bodies with `{ ... }`
+    #[arg(long)]
+    skeleton: bool,
+
+    /// Replace Python, JS/TS, Go, Java and C/C++ files with their declarations and line numbers,
+    /// and reduce Rust files as with --skeleton
+    #[arg(long)]
+    outline: bool,
+
+    /// Pattern(s) for files kept in full by --outline and --skeleton (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',')]
+    focus: Vec<String>,
+
+    /// Remove comments from source files (Rust, Python, JS/TS, Go, C-family, shell, TOML, YAML)
+    #[arg(long)]
+    strip_comments: bool,
+
+    /// Trim trailing whitespace and collapse runs of blank lines
+    #[arg(long)]
+    compact: bool,
+
+    /// Prefix each line of file content with its line number in the original file
+    #[arg(
+        long,
+        conflicts_with_all = ["strip_comments", "compact", "skeleton", "outline"]
+    )]
+    line_numbers: bool,
+
+    /// Text placed between a line number and the line
+    #[arg(
+        long,
+        value_name = "SEP",
+        default_value = " | ",
+        requires = "line_numbers"
+    )]
+    line_number_separator: String,
+
+    /// How to handle files that look like they contain secrets [default: mask]
+    #[arg(long, value_enum)]
+    secrets: Option<SecretMode>,
+
+    /// How to handle binary files
+    #[arg(long, value_enum, default_value_t)]
+    binary: BinaryMode,
+
+    /// Skip files larger than this size (e.g. 512K, 2MB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Skip files that would push the combined size past this limit (e.g. 10MB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_total_size: Option<u64>,
+
+    /// Print the rule or filter that excluded each path, then exit
+    #[arg(long)]
+    explain: bool,
+
+    /// Print whether a single path is included and why, then exit
+    #[arg(long, value_name = "PATH")]
+    why: Option<PathBuf>,
+
+    /// Print the selected files with sizes and estimated tokens, then exit
+    #[arg(long, visible_alias = "dry-run")]
+    list: bool,
+
+    /// Apply a named `[profile.NAME]` from the config files
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Print the settings merged from config files and flags, then exit
+    #[arg(long)]
+    print_config: bool,
 }
 
-fn main() -> Result<()> {
-    // Initialize the logger
-    env_logger::init();
-
-    let args = Args::parse();
-
-    info!("Starting file scan...");
-
-    // Determine the root path
-    let root_path = args.path.unwrap_or_else(|| PathBuf::from("."));
-    let root_path = fs::canonicalize(&root_path)?;
-    info!("Scanning directory: {}", root_path.display());
-
-    // Build the walker with ignore files
-    let mut walker = WalkBuilder::new(&root_path);
-
-    // Add .gitignore patterns
-    if Path::new(".gitignore").exists() {
-        info!("Found .gitignore - applying ignore patterns");
-        walker.add_ignore(Path::new(".gitignore"));
+impl Args {
+    /// The settings given on the command line, in config form
+    fn config(&self) -> Config {
+        Config {
+            paths: self.path.iter().chain(&self.paths).cloned().collect(),
+            include: self.match_pattern.clone(),
+            exclude: self.exclude.clone(),
+            format: self.format,
+            template: self.template.clone(),
+            file_template: self.file_template.clone(),
+            task: self.task.clone(),
+            max_tokens: self.max_tokens,
+            tokenizer: self.tokenizer,
+            budget_policy: self.budget_policy,
+            priority: self.priority.clone(),
+            output: if self.stdout {
+                Some(PathBuf::from("-"))
+            } else {
+                self.output_file.clone()
+            },
+            clipboard: self.clipboard.clone(),
+            secrets: SecretsConfig {
+                mode: self.secrets,
+                ..Default::default()
+            },
+            profile: Default::default(),
+        }
     }
 
-    // Add .preludeignore patterns
-    if Path::new(".preludeignore").exists() {
-        info!("Found .preludeignore - applying ignore patterns");
-        walker.add_ignore(Path::new(".preludeignore"));
+    /// Replaces the config-backed settings with the merged config
+    fn apply(&mut self, config: Config) {
+        self.path = config.paths;
+        self.paths.clear();
+        self.match_pattern = config.include;
+        self.exclude = config.exclude;
+        self.format = config.format;
+        self.template = config.template;
+        self.file_template = config.file_template;
+        self.task = config.task;
+        self.max_tokens = config.max_tokens;
+        self.tokenizer = config.tokenizer;
+        self.budget_policy = config.budget_policy;
+        self.priority = config.priority;
+        self.output_file = config.output;
+        self.clipboard = config.clipboard;
     }
+}
 
-    // Configure git integration
-    if args.git_only {
-        info!("Git-only mode enabled - only including tracked files");
-        walker.git_ignore(true);
-        walker.git_global(true);
-        walker.git_exclude(true);
-    }
+/// Parses a human readable size such as `512`, `64K`, `1.5MB` or `2GiB`
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("unknown size unit in '{}'", value)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
 
-    // Set case sensitivity
-    walker.ignore_case_insensitive(!args.case_sensitive);
-    if args.case_sensitive {
-        info!("Case-sensitive matching enabled");
+fn main() -> Result<()> {
+    // Initialize the logger, keeping stdout free for the prompt
+    env_logger::Builder::from_default_env()
+        .target(env_logger::Target::Stderr)
+        .init();
+
+    let mut args = Args::parse();
+
+    // Layer command line flags over the user and project config files
+    // The project config is looked up from the first scanned path given on the command line
+    let start = args
+        .path
+        .iter()
+        .chain(&args.paths)
+        .next()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let (mut file_config, config_sources) = Config::load(&start)?;
+    if let Some(ref profile) = args.profile {
+        info!("Using profile: {}", profile);
+        file_config = file_config.select(profile)?;
     }
-
-    info!("Collecting files...");
-
-    // Collect all valid files
-    let mut files: Vec<PathBuf> = Vec::new();
-    let mut ignored_files: Vec<PathBuf> = Vec::new();
-
-    // First, get all entries without ignoring any
-    let all_entries = WalkBuilder::new(&root_path)
-        .git_ignore(args.git_only)
-        .git_global(args.git_only)
-        .git_exclude(args.git_only)
-        .ignore_case_insensitive(!args.case_sensitive)
-        .build()
-        .map(|r| r.map_err(anyhow::Error::from))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Then get entries with ignoring enabled
-    let filtered_entries = walker
-        .build()
-        .map(|r| r.map_err(anyhow::Error::from))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Find ignored files by comparing the two sets of entries
-    'outer: for entry in all_entries {
-        let path = entry.path().strip_prefix(&root_path).unwrap().to_path_buf();
-        for filtered_entry in &filtered_entries {
-            let filtered_path = filtered_entry
-                .path()
-                .strip_prefix(&root_path)
-                .unwrap()
-                .to_path_buf();
-            if path == filtered_path {
-                continue 'outer;
-            }
+    let config = file_config.merge(args.config());
+    if args.print_config {
+        for source in &config_sources {
+            println!("# Loaded from {}", source.display());
         }
-        ignored_files.push(path);
+        print!("{}", config.to_toml()?);
+        return Ok(());
     }
-
-    // Now collect the filtered files
-    for entry in filtered_entries {
-        if entry.file_type().map_or(false, |ft| ft.is_file()) {
-            if let Ok(path) = entry.path().strip_prefix(&root_path) {
-                debug!("Reading: {}", path.display());
-                files.push(path.to_path_buf());
-            }
+    let secrets = config.secrets.clone();
+    args.apply(config);
+
+    // Load prompt templates up front so a bad name fails before scanning
+    let prompt_template = args.template.as_deref().map(template::load).transpose()?;
+    let file_template = args
+        .file_template
+        .as_deref()
+        .map(template::load)
+        .transpose()?;
+
+    // Restrict to files changed relative to a git ref
+    let changes = if let Some(ref reference) = args.since {
+        Some(DiffTarget::Since(reference.clone()))
+    } else if args.staged {
+        Some(DiffTarget::Staged)
+    } else if args.working {
+        Some(DiffTarget::Working)
+    } else {
+        None
+    };
+
+    // Reports print to stdout instead of writing the prompt anywhere
+    let report = args.why.is_some() || args.explain || args.list;
+    let sink = if report {
+        None
+    } else if args.stdout || args.output_file.as_deref() == Some(Path::new("-")) {
+        Some(Sink::Stdout)
+    } else if let Some(path) = args.output_file {
+        Some(Sink::File(path))
+    } else {
+        Some(Sink::Clipboard(args.clipboard.unwrap_or_default()))
+    };
+
+    let pack = Packer::new()
+        .roots(args.path)
+        .include(args.match_pattern)
+        .exclude(args.exclude)
+        .case_sensitive(args.case_sensitive)
+        .git_only(args.git_only)
+        .changes(changes)
+        .diff(args.diff)
+        .binary(args.binary)
+        .max_file_size(args.max_file_size)
+        .max_total_size(args.max_total_size)
+        .secrets(secrets)
+        .skeleton(args.skeleton)
+        .outline(args.outline)
+        .focus(args.focus)
+        .strip_comments(args.strip_comments)
+        .compact(args.compact)
+        .line_numbers(args.line_numbers.then_some(args.line_number_separator))
+        .tokenizer(args.tokenizer.unwrap_or_default())
+        .max_tokens(args.max_tokens)
+        .budget_policy(args.budget_policy.unwrap_or_default())
+        .priority(args.priority)
+        .tree_style(TreeStyle {
+            ascii: args.ascii,
+            sizes: args.tree_sizes,
+            line_counts: args.tree_lines,
+        })
+        .format(args.format.unwrap_or_default())
+        .template(prompt_template)
+        .file_template(file_template)
+        .task(args.task)
+        .sink(sink)
+        .dry_run(report)
+        .pack()?;
+
+    // Always say what was masked, whatever the log level
+    if !pack.redacted.is_empty() {
+        eprintln!("Redacted {} secret(s):", pack.redacted.len());
+        for (path, finding) in &pack.redacted {
+            eprintln!("├── {}:{} ({})", path.display(), finding.line, finding.rule);
         }
     }
 
-    // Debug log ignored files
-    if !ignored_files.is_empty() {
-        debug!("Ignored files:");
-        for file in &ignored_files {
-            debug!("├── {}", file.display());
-        }
+    // Report why paths were excluded instead of building a prompt
+    if let Some(ref query) = args.why {
+        print!("{}", pack.why(query)?);
+        return Ok(());
     }
-
-    files.sort();
-
-    info!("Building file tree...");
-    let tree = build_tree(&files);
-
-    info!("Reading file contents...");
-
-    let mut concatenated = String::new();
-    for file in &files {
-        let full_path = root_path.join(file);
-        match fs::read_to_string(&full_path) {
-            Ok(content) => {
-                debug!("Processing: {}", file.display());
-                concatenated.push_str(&format!(
-                    "\n\n--- File: {} ---\n\n{}",
-                    file.display(),
-                    content
-                ));
-            }
-            Err(err) => error!("Error reading {}: {}", file.display(), err),
-        }
+    if args.explain {
+        print!("{}", pack.explain());
+        return Ok(());
     }
-
-    info!("Building final prompt...");
-
-    let prompt = format!(
-        "I want you to help me fix some issues with my code.\n
-I have attached the code and file structure.\n\n
-File Tree:\n{}\n
-Concatenated Files:{}",
-        tree, concatenated
-    );
-
-    if let Some(ref output_file) = args.output_file {
-        info!("Saving to file: {}", output_file.display());
-        fs::write(&output_file, &prompt).context("Failed to write output file")?;
-        info!("Successfully saved prompt to {}", output_file.display());
+    if args.list {
+        print!("{}", pack.listing());
+        return Ok(());
     }
 
-    // Only copy to clipboard if not saving to file
-    if args.output_file.is_none() {
-        info!("Copying prompt to clipboard...");
-        let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-        match ctx.set_contents(prompt.to_owned()) {
-            Ok(_) => info!("Prompt copied to clipboard successfully!"),
-            Err(err) => error!("Failed to copy prompt to clipboard: {}", err),
+    // Summarize everything that was left out, whatever the log level
+    let summary: Vec<&SkippedFile> = pack.skipped.iter().filter(|f| f.reason.in_tree()).collect();
+    if !summary.is_empty() {
+        eprintln!("Excluded {} file(s):", summary.len());
+        for file in summary {
+            eprintln!("├── {} ({})", file.path.display(), file.reason);
         }
     }
 
@@ -0,0 +1,443 @@
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+use crate::format::language_for;
+
+/// What a braced block in a declaration's header is replaced with
+const ELIDED_BLOCK: &str = "{ ... }";
+
+/// Which nodes of a tree-sitter grammar make up a file's outline
+struct Grammar {
+    language: Language,
+    /// Declarations listed in the outline
+    items: &'static [&'static str],
+    /// Listed declarations whose bodies hold further declarations, like classes
+    scopes: &'static [&'static str],
+    /// Nodes searched for declarations without being listed themselves
+    containers: &'static [&'static str],
+    /// Containers whose text is kept in front of the declaration they hold, like `export`
+    prefixes: &'static [&'static str],
+}
+
+const JS_ITEMS: &[&str] = &[
+    "class_declaration",
+    "function_declaration",
+    "generator_function_declaration",
+    "method_definition",
+    "variable_declarator",
+];
+
+const TS_ITEMS: &[&str] = &[
+    "class_declaration",
+    "abstract_class_declaration",
+    "function_declaration",
+    "generator_function_declaration",
+    "function_signature",
+    "method_definition",
+    "method_signature",
+    "abstract_method_signature",
+    "variable_declarator",
+    "interface_declaration",
+    "enum_declaration",
+    "type_alias_declaration",
+    "internal_module",
+    "module",
+];
+
+const C_ITEMS: &[&str] = &[
+    "function_definition",
+    "declaration",
+    "struct_specifier",
+    "union_specifier",
+    "enum_specifier",
+    "type_definition",
+];
+
+const CPP_ITEMS: &[&str] = &[
+    "function_definition",
+    "declaration",
+    "field_declaration",
+    "struct_specifier",
+    "union_specifier",
+    "enum_specifier",
+    "class_specifier",
+    "type_definition",
+    "namespace_definition",
+];
+
+/// Preprocessor conditionals, which wrap whole headers behind include guards
+const PREPROC: &[&str] = &[
+    "preproc_if",
+    "preproc_ifdef",
+    "preproc_else",
+    "preproc_elif",
+    "preproc_elifdef",
+];
+
+/// Looks up the grammar for a file, or `None` for languages without one
+fn grammar_for(path: &Path) -> Option<Grammar> {
+    let grammar = match language_for(path) {
+        "python" => Grammar {
+            language: tree_sitter_python::LANGUAGE.into(),
+            items: &["class_definition", "function_definition"],
+            scopes: &["class_definition"],
+            containers: &["decorated_definition"],
+            prefixes: &[],
+        },
+        "javascript" | "jsx" => Grammar {
+            language: tree_sitter_javascript::LANGUAGE.into(),
+            items: JS_ITEMS,
+            scopes: &["class_declaration"],
+            containers: &[
+                "export_statement",
+                "lexical_declaration",
+                "variable_declaration",
+            ],
+            prefixes: &[
+                "export_statement",
+                "lexical_declaration",
+                "variable_declaration",
+            ],
+        },
+        language @ ("typescript" | "tsx") => Grammar {
+            language: if language == "tsx" {
+                tree_sitter_typescript::LANGUAGE_TSX.into()
+            } else {
+                tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()
+            },
+            items: TS_ITEMS,
+            scopes: &[
+                "class_declaration",
+                "abstract_class_declaration",
+                "interface_declaration",
+                "internal_module",
+                "module",
+            ],
+            containers: &[
+                "export_statement",
+                "lexical_declaration",
+                "variable_declaration",
+                "ambient_declaration",
+                "expression_statement",
+            ],
+            prefixes: &[
+                "export_statement",
+                "lexical_declaration",
+                "variable_declaration",
+                "ambient_declaration",
+            ],
+        },
+        "go" => Grammar {
+            language: tree_sitter_go::LANGUAGE.into(),
+            items: &[
+                "function_declaration",
+                "method_declaration",
+                "type_declaration",
+            ],
+            scopes: &[],
+            containers: &[],
+            prefixes: &[],
+        },
+        "java" => Grammar {
+            language: tree_sitter_java::LANGUAGE.into(),
+            items: &[
+                "class_declaration",
+                "interface_declaration",
+                "enum_declaration",
+                "record_declaration",
+                "annotation_type_declaration",
+                "method_declaration",
+                "constructor_declaration",
+                "compact_constructor_declaration",
+            ],
+            scopes: &[
+                "class_declaration",
+                "interface_declaration",
+                "enum_declaration",
+                "record_declaration",
+            ],
+            containers: &["enum_body_declarations"],
+            prefixes: &[],
+        },
+        "c" => Grammar {
+            language: tree_sitter_c::LANGUAGE.into(),
+            items: C_ITEMS,
+            scopes: &[],
+            containers: PREPROC,
+            prefixes: &[],
+        },
+        "cpp" => Grammar {
+            language: tree_sitter_cpp::LANGUAGE.into(),
+            items: CPP_ITEMS,
+            scopes: &[
+                "struct_specifier",
+                "union_specifier",
+                "class_specifier",
+                "namespace_definition",
+            ],
+            containers: &[
+                "preproc_if",
+                "preproc_ifdef",
+                "preproc_else",
+                "preproc_elif",
+                "preproc_elifdef",
+                "template_declaration",
+                "linkage_specification",
+                "declaration_list",
+            ],
+            prefixes: &["template_declaration"],
+        },
+        _ => return None,
+    };
+    Some(grammar)
+}
+
+/// Whether a declarator declares a function, looking through pointers and references
+fn declares_function(node: Node) -> bool {
+    let mut declarator = node.child_by_field_name("declarator");
+    while let Some(node) = declarator {
+        if node.kind() == "function_declarator" {
+            return true;
+        }
+        declarator = node.child_by_field_name("declarator");
+    }
+    false
+}
+
+/// Whether a node of one of the grammar's item kinds belongs in the outline
+fn listed(node: Node) -> bool {
+    match node.kind() {
+        // Variables only count when they hold a function
+        "variable_declarator" => node.child_by_field_name("value").is_some_and(|value| {
+            matches!(
+                value.kind(),
+                "arrow_function" | "function_expression" | "function" | "generator_function"
+            )
+        }),
+        // Prototypes and method declarations, but not plain variables or fields
+        "declaration" | "field_declaration" => declares_function(node),
+        // Type names and forward declarations are left out
+        "struct_specifier" | "union_specifier" | "enum_specifier" | "class_specifier" => {
+            node.child_by_field_name("body").is_some()
+        }
+        _ => true,
+    }
+}
+
+/// The node holding a declaration's body, including the body of a function assigned to a variable
+fn body_of(node: Node) -> Option<Node> {
+    node.child_by_field_name("body").or_else(|| {
+        node.child_by_field_name("value")
+            .and_then(|value| value.child_by_field_name("body"))
+    })
+}
+
+/// Records the byte range of each comment inside `node` that starts before `end`
+fn comments_before(node: Node, end: usize, out: &mut Vec<(usize, usize)>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.start_byte() >= end {
+            break;
+        }
+        if child.kind().contains("comment") {
+            out.push((child.start_byte(), child.end_byte().min(end)));
+        } else {
+            comments_before(child, end, out);
+        }
+    }
+}
+
+/// A declaration's text from `start` up to its body, collapsed onto one line without comments
+///
+/// Declarations without a body node, like Go types and C typedefs, keep their
+/// text with each braced block outside of parentheses replaced by `{ ... }`.
+fn header(node: Node, start: usize, source: &str) -> String {
+    let end = body_of(node)
+        .map_or(node.end_byte(), |body| body.start_byte())
+        .max(start);
+
+    let mut comments = Vec::new();
+    comments_before(node, end, &mut comments);
+    let mut text = String::with_capacity(end - start);
+    let mut last = start;
+    for (comment_start, comment_end) in comments {
+        if comment_start >= last {
+            text.push_str(&source[last..comment_start]);
+            text.push(' ');
+            last = comment_end;
+        }
+    }
+    text.push_str(&source[last..end]);
+
+    let mut kept = String::with_capacity(text.len());
+    let mut parens = 0i32;
+    let mut braces = 0i32;
+    for c in text.chars() {
+        match c {
+            '{' if parens <= 0 => {
+                if braces == 0 {
+                    kept.push_str(ELIDED_BLOCK);
+                }
+                braces += 1;
+            }
+            '}' if braces > 0 => braces -= 1,
+            _ if braces > 0 => {}
+            '(' | '[' => {
+                parens += 1;
+                kept.push(c);
+            }
+            ')' | ']' => {
+                parens -= 1;
+                kept.push(c);
+            }
+            _ => kept.push(c),
+        }
+    }
+
+    let header = kept.split_whitespace().collect::<Vec<_>>().join(" ");
+    header.trim_end_matches([';', ' ']).to_string()
+}
+
+/// Collects `(line, depth, header)` for each declaration under `node`
+fn collect(
+    grammar: &Grammar,
+    node: Node,
+    prefix: Option<usize>,
+    depth: usize,
+    source: &str,
+    out: &mut Vec<(usize, usize, String)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let kind = child.kind();
+        let start = prefix.unwrap_or(child.start_byte());
+
+        if grammar.items.contains(&kind) && listed(child) {
+            let line = source[..start].matches('\n').count() + 1;
+            out.push((line, depth, header(child, start, source)));
+            if grammar.scopes.contains(&kind) {
+                if let Some(body) = child.child_by_field_name("body") {
+                    collect(grammar, body, None, depth + 1, source, out);
+                }
+            }
+        } else if grammar.containers.contains(&kind) {
+            let prefix = grammar.prefixes.contains(&kind).then_some(start);
+            collect(grammar, child, prefix, depth, source, out);
+        }
+    }
+}
+
+/// Lists the classes, functions and methods in a file with the line each starts on
+///
+/// Returns `None` for languages without a grammar, for files that fail to parse and
+/// for files without any declarations, so their content can be kept instead.
+pub fn outline(path: &Path, content: &str) -> Option<String> {
+    let grammar = grammar_for(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language).ok()?;
+    let tree = parser.parse(content, None)?;
+
+    let mut entries = Vec::new();
+    collect(&grammar, tree.root_node(), None, 0, content, &mut entries);
+    if entries.is_empty() {
+        return None;
+    }
+
+    let width = entries
+        .last()
+        .map_or(1, |(line, _, _)| line.to_string().len());
+    let mut out = String::new();
+    for (line, depth, header) in entries {
+        out.push_str(&format!(
+            "{:>width$}: {}{}\n",
+            line,
+            "  ".repeat(depth),
+            header,
+            width = width
+        ));
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline_of(name: &str, content: &str) -> Option<String> {
+        outline(Path::new(name), content)
+    }
+
+    #[test]
+    fn python_classes_and_methods() {
+        let content = "import os\n\nclass Foo(Base):\n    x = 1\n\n    @property\n    def name(self): # c\n        def inner():\n            pass\n        return 1\n\ndef top(a,\n        b):\n    pass\n";
+        assert_eq!(
+            outline_of("a.py", content).unwrap(),
+            " 3: class Foo(Base):\n 7:   def name(self):\n12: def top(a, b):\n"
+        );
+    }
+
+    #[test]
+    fn typescript_declarations() {
+        let content = "export interface Options {\n  run(opts: { fast: boolean }): void;\n}\n\
+                       type Shape = { w: number };\n\
+                       export const handler = async (req: Request) => {\n  return 1;\n};\n\
+                       const value = 42;\n\
+                       class Box {\n  // the value\n  get(): number { return 1; }\n}\n";
+        let expected = [
+            " 1: export interface Options",
+            " 2:   run(opts: { fast: boolean }): void",
+            " 4: type Shape = { ... }",
+            " 5: export const handler = async (req: Request) =>",
+            " 9: class Box",
+            "11:   get(): number",
+        ];
+        assert_eq!(
+            outline_of("a.ts", content).unwrap(),
+            expected.join("\n") + "\n"
+        );
+    }
+
+    #[test]
+    fn go_functions_and_types() {
+        let content = "package main\n\ntype Server struct {\n\taddr string\n}\n\n\
+                       func (s *Server) Start() error {\n\treturn nil\n}\n";
+        assert_eq!(
+            outline_of("a.go", content).unwrap(),
+            "3: type Server struct { ... }\n7: func (s *Server) Start() error\n"
+        );
+    }
+
+    #[test]
+    fn java_nested_classes() {
+        let content = "class D {\n  D(int x) {}\n  static class Inner { void go() {} }\n}\n";
+        assert_eq!(
+            outline_of("D.java", content).unwrap(),
+            "1: class D\n2:   D(int x)\n3:   static class Inner\n3:     void go()\n"
+        );
+    }
+
+    #[test]
+    fn c_header_behind_include_guard() {
+        let content = "#ifndef E_H\n#define E_H\nstruct point { int x; };\nstruct fwd;\n\
+                       int add(int a, int b);\nextern int counter;\n#endif\n";
+        assert_eq!(
+            outline_of("e.h", content).unwrap(),
+            "3: struct point\n5: int add(int a, int b)\n"
+        );
+    }
+
+    #[test]
+    fn cpp_namespaces_and_templates() {
+        let content = "namespace ns {\ntemplate <typename T>\nclass Box {\npublic:\n    T get() const { return v; }\n    void set(T v);\n    T v;\n};\n}\n";
+        assert_eq!(
+            outline_of("a.cpp", content).unwrap(),
+            "1: namespace ns\n2:   template <typename T> class Box\n5:     T get() const\n6:     void set(T v)\n"
+        );
+    }
+
+    #[test]
+    fn files_without_declarations_are_kept() {
+        assert_eq!(outline_of("n.py", "print('hello')\n"), None);
+        assert_eq!(outline_of("a.rb", "def f; end\n"), None);
+    }
+}
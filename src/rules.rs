@@ -0,0 +1,296 @@
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use log::error;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Ignore files read in every directory, highest precedence first
+const IGNORE_FILENAMES: [&str; 3] = [crate::PRELUDE_IGNORE, ".ignore", ".gitignore"];
+
+/// The ignore pattern that excluded a path
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub source: PathBuf,
+    pub line: Option<usize>,
+    pub pattern: String,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.source.display(), line, self.pattern),
+            None => write!(f, "{}: {}", self.source.display(), self.pattern),
+        }
+    }
+}
+
+/// A compiled ignore file along with the line each pattern came from
+struct Matcher {
+    gitignore: Gitignore,
+    lines: HashMap<String, usize>,
+}
+
+impl Matcher {
+    /// Compiles an ignore file whose patterns are relative to `root`
+    fn load(root: &Path, source: &Path, case_insensitive: bool) -> Option<Matcher> {
+        let text = fs::read_to_string(source).ok()?;
+        let mut builder = GitignoreBuilder::new(root);
+        builder.case_insensitive(case_insensitive).ok()?;
+
+        let mut lines = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            if let Err(err) = builder.add_line(Some(source.to_path_buf()), line) {
+                error!("Invalid pattern in {}: {}", source.display(), err);
+                continue;
+            }
+
+            // Mirror the trimming GitignoreBuilder applies to a glob's original text
+            let key = if line.ends_with("\\ ") {
+                line
+            } else {
+                line.trim_end()
+            };
+            lines.insert(key.to_string(), index + 1);
+        }
+
+        match builder.build() {
+            Ok(gitignore) if !gitignore.is_empty() => Some(Matcher { gitignore, lines }),
+            Ok(_) => None,
+            Err(err) => {
+                error!("Failed to load {}: {}", source.display(), err);
+                None
+            }
+        }
+    }
+
+    /// Returns `Some(Some(rule))` when ignored, `Some(None)` when whitelisted
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<Option<Rule>> {
+        match self.gitignore.matched(path, is_dir) {
+            Match::None => None,
+            Match::Whitelist(_) => Some(None),
+            Match::Ignore(glob) => Some(Some(Rule {
+                source: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+                line: self.lines.get(glob.original()).copied(),
+                pattern: glob.original().to_string(),
+            })),
+        }
+    }
+}
+
+/// The ignore files found in a single directory, in precedence order
+struct DirRules {
+    matchers: Vec<Option<Matcher>>,
+}
+
+/// Hierarchical .preludeignore, .ignore and .gitignore rules that report which pattern matched
+///
+/// Ignore files are read lazily per directory and cached, so a single walk can
+/// consult them for every entry.
+pub struct IgnoreRules {
+    case_insensitive: bool,
+    /// The ignore files read in each directory, highest precedence first
+    filenames: &'static [&'static str],
+    /// The directory where ignore files stop being inherited from parents
+    top: Option<PathBuf>,
+    exclude: Option<Matcher>,
+    global: Option<Matcher>,
+    cache: Mutex<HashMap<PathBuf, Arc<DirRules>>>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &Path, case_insensitive: bool) -> Self {
+        // Inherit rules from parents up to the enclosing git repository, if any
+        let top = root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf);
+
+        let exclude = top
+            .as_ref()
+            .and_then(|top| Matcher::load(top, &top.join(".git/info/exclude"), case_insensitive));
+
+        let (global, err) = Gitignore::global();
+        if let Some(err) = err {
+            error!("Failed to load global gitignore: {}", err);
+        }
+        let global = (!global.is_empty()).then(|| Matcher {
+            gitignore: global,
+            lines: HashMap::new(),
+        });
+
+        IgnoreRules {
+            case_insensitive,
+            filenames: &IGNORE_FILENAMES,
+            top,
+            exclude,
+            global,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Rules from .preludeignore files alone, for when git already decided what is tracked
+    pub fn prelude_only(root: &Path, case_insensitive: bool) -> Self {
+        IgnoreRules {
+            filenames: &IGNORE_FILENAMES[..1],
+            exclude: None,
+            global: None,
+            ..IgnoreRules::new(root, case_insensitive)
+        }
+    }
+
+    fn dir_rules(&self, dir: &Path) -> Arc<DirRules> {
+        let mut cache = self.cache.lock().unwrap();
+        cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                Arc::new(DirRules {
+                    matchers: self
+                        .filenames
+                        .iter()
+                        .map(|name| Matcher::load(dir, &dir.join(name), self.case_insensitive))
+                        .collect(),
+                })
+            })
+            .clone()
+    }
+
+    /// Returns the rule that ignores `path`, or `None` when it should be kept
+    ///
+    /// Like git, each kind of ignore file is checked from the deepest directory
+    /// upwards and the first match wins, so `!` negations apply per directory.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<Rule> {
+        let mut dirs = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            dirs.push(self.dir_rules(dir));
+            if self.top.as_deref() == Some(dir) {
+                break;
+            }
+        }
+
+        for kind in 0..self.filenames.len() {
+            for rules in &dirs {
+                if let Some(matched) = rules.matchers[kind]
+                    .as_ref()
+                    .and_then(|matcher| matcher.matched(path, is_dir))
+                {
+                    return matched;
+                }
+            }
+        }
+
+        for matcher in [&self.exclude, &self.global].into_iter().flatten() {
+            if let Some(matched) = matcher.matched(path, is_dir) {
+                return matched;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a repository with the given files, returning its directory
+    fn repo(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn reports_the_matching_line() {
+        let dir = repo(&[(".gitignore", "# build output\ntarget/\n*.log\n")]);
+        let rules = IgnoreRules::new(dir.path(), false);
+
+        let rule = rules.matched(&dir.path().join("debug.log"), false).unwrap();
+        assert_eq!(rule.source, dir.path().join(".gitignore"));
+        assert_eq!(rule.line, Some(3));
+        assert_eq!(rule.pattern, "*.log");
+
+        let rule = rules.matched(&dir.path().join("target"), true).unwrap();
+        assert_eq!(rule.line, Some(2));
+        assert!(rules.matched(&dir.path().join("main.rs"), false).is_none());
+    }
+
+    #[test]
+    fn nested_negation_wins_over_parent() {
+        let dir = repo(&[
+            (".gitignore", "*.log\n"),
+            ("sub/.gitignore", "!important.log\n"),
+        ]);
+        let rules = IgnoreRules::new(dir.path(), false);
+
+        assert!(rules
+            .matched(&dir.path().join("sub/important.log"), false)
+            .is_none());
+        let rule = rules
+            .matched(&dir.path().join("sub/other.log"), false)
+            .unwrap();
+        assert_eq!(rule.source, dir.path().join(".gitignore"));
+        assert_eq!(rule.line, Some(1));
+    }
+
+    #[test]
+    fn preludeignore_takes_precedence() {
+        let dir = repo(&[
+            (".gitignore", "*.json\n"),
+            (crate::PRELUDE_IGNORE, "\n!package.json\nsecret.txt\n"),
+        ]);
+        let rules = IgnoreRules::new(dir.path(), false);
+
+        assert!(rules
+            .matched(&dir.path().join("package.json"), false)
+            .is_none());
+        assert!(rules
+            .matched(&dir.path().join("data.json"), false)
+            .is_some());
+        let rule = rules
+            .matched(&dir.path().join("secret.txt"), false)
+            .unwrap();
+        assert_eq!(rule.source, dir.path().join(crate::PRELUDE_IGNORE));
+        assert_eq!(rule.line, Some(3));
+    }
+
+    #[test]
+    fn prelude_only_skips_git_ignore_files() {
+        let dir = repo(&[
+            (".gitignore", "*.log\n"),
+            (".git/info/exclude", "*.tmp\n"),
+            (crate::PRELUDE_IGNORE, "*.bak\n"),
+        ]);
+        let rules = IgnoreRules::prelude_only(dir.path(), false);
+
+        assert!(rules
+            .matched(&dir.path().join("debug.log"), false)
+            .is_none());
+        assert!(rules
+            .matched(&dir.path().join("scratch.tmp"), false)
+            .is_none());
+        assert!(rules.matched(&dir.path().join("old.bak"), false).is_some());
+    }
+
+    #[test]
+    fn case_insensitive_matching() {
+        let dir = repo(&[(".gitignore", "*.LOG\n")]);
+
+        assert!(IgnoreRules::new(dir.path(), true)
+            .matched(&dir.path().join("debug.log"), false)
+            .is_some());
+        assert!(IgnoreRules::new(dir.path(), false)
+            .matched(&dir.path().join("debug.log"), false)
+            .is_none());
+    }
+}
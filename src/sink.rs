@@ -0,0 +1,419 @@
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use log::{debug, info, warn};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    env, fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+/// Payloads above this size are often silently dropped by terminals handling OSC 52
+const OSC52_WARN_LEN: usize = 100_000;
+
+/// A way of putting text on the user's clipboard
+pub trait ClipboardBackend {
+    /// Short name used in logs and errors
+    fn name(&self) -> &'static str;
+
+    /// Whether the environment looks like this backend can work, checked before trying it
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Copies `text` to the clipboard
+    fn copy(&self, text: &str) -> Result<()>;
+}
+
+/// The platform clipboard: X11 on Linux, the system pasteboard elsewhere
+pub struct Native;
+
+impl ClipboardBackend for Native {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn is_available(&self) -> bool {
+        !cfg!(target_os = "linux") || env::var_os("DISPLAY").is_some()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        let mut ctx: ClipboardContext =
+            ClipboardProvider::new().map_err(|err| anyhow!("{}", err))?;
+        ctx.set_contents(text.to_owned())
+            .map_err(|err| anyhow!("{}", err))
+    }
+}
+
+/// A helper program that reads the clipboard contents from stdin
+pub struct External {
+    name: &'static str,
+    program: &'static str,
+    args: &'static [&'static str],
+    /// Environment variable that must be set for the program to reach a clipboard
+    requires_env: &'static str,
+}
+
+impl External {
+    pub const WAYLAND: External = External {
+        name: "wayland",
+        program: "wl-copy",
+        args: &[],
+        requires_env: "WAYLAND_DISPLAY",
+    };
+    pub const XCLIP: External = External {
+        name: "xclip",
+        program: "xclip",
+        args: &["-selection", "clipboard"],
+        requires_env: "DISPLAY",
+    };
+    pub const XSEL: External = External {
+        name: "xsel",
+        program: "xsel",
+        args: &["--clipboard", "--input"],
+        requires_env: "DISPLAY",
+    };
+    pub const TMUX: External = External {
+        name: "tmux",
+        program: "tmux",
+        args: &["load-buffer", "-"],
+        requires_env: "TMUX",
+    };
+}
+
+impl ClipboardBackend for External {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn is_available(&self) -> bool {
+        env::var_os(self.requires_env).is_some()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        // xclip and wl-copy fork a process that serves the selection and keeps the inherited
+        // output open, so only the exit status is waited for
+        let mut child = Command::new(self.program)
+            .args(self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+            // Closing stdin tells the program the text is complete
+            drop(stdin);
+        }
+
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} exited with {}", self.program, status);
+        }
+        Ok(())
+    }
+}
+
+/// OSC 52 escape sequence written to the terminal, which works over SSH
+pub struct Osc52;
+
+impl ClipboardBackend for Osc52 {
+    fn name(&self) -> &'static str {
+        "osc52"
+    }
+
+    fn is_available(&self) -> bool {
+        fs::OpenOptions::new().write(true).open("/dev/tty").is_ok()
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        if text.len() > OSC52_WARN_LEN {
+            warn!(
+                "Prompt is {} bytes; some terminals drop OSC 52 payloads this large",
+                text.len()
+            );
+        }
+
+        let mut sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+        // tmux only forwards escape sequences to the outer terminal when wrapped
+        if env::var_os("TMUX").is_some() {
+            sequence = format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"));
+        }
+
+        let mut tty = fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .context("Failed to open /dev/tty")?;
+        tty.write_all(sequence.as_bytes())?;
+        tty.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the clipboard contents to a file, standing in for a real clipboard
+pub struct FileClipboard(pub PathBuf);
+
+impl ClipboardBackend for FileClipboard {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        fs::write(&self.0, text).with_context(|| format!("Failed to write {}", self.0.display()))
+    }
+}
+
+/// The clipboard backend selected with `--clipboard`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ClipboardChoice {
+    /// Try each backend in turn until one succeeds
+    #[default]
+    Auto,
+    X11,
+    Wayland,
+    Xclip,
+    Xsel,
+    Tmux,
+    Osc52,
+    File(PathBuf),
+}
+
+impl FromStr for ClipboardChoice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = value.strip_prefix("file:") {
+            return Ok(ClipboardChoice::File(PathBuf::from(path)));
+        }
+        match value {
+            "auto" => Ok(ClipboardChoice::Auto),
+            "x11" | "native" => Ok(ClipboardChoice::X11),
+            "wayland" | "wl-copy" => Ok(ClipboardChoice::Wayland),
+            "xclip" => Ok(ClipboardChoice::Xclip),
+            "xsel" => Ok(ClipboardChoice::Xsel),
+            "tmux" => Ok(ClipboardChoice::Tmux),
+            "osc52" => Ok(ClipboardChoice::Osc52),
+            _ => Err(format!(
+                "unknown clipboard backend '{}' (expected auto, x11, wayland, xclip, xsel, \
+                 tmux, osc52 or file:PATH)",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ClipboardChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClipboardChoice::Auto => write!(f, "auto"),
+            ClipboardChoice::X11 => write!(f, "x11"),
+            ClipboardChoice::Wayland => write!(f, "wayland"),
+            ClipboardChoice::Xclip => write!(f, "xclip"),
+            ClipboardChoice::Xsel => write!(f, "xsel"),
+            ClipboardChoice::Tmux => write!(f, "tmux"),
+            ClipboardChoice::Osc52 => write!(f, "osc52"),
+            ClipboardChoice::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+impl Serialize for ClipboardChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClipboardChoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl ClipboardChoice {
+    /// The backends to try, in order
+    fn backends(&self) -> Vec<Box<dyn ClipboardBackend>> {
+        match self {
+            ClipboardChoice::Auto => vec![
+                Box::new(Native),
+                Box::new(External::WAYLAND),
+                Box::new(External::XCLIP),
+                Box::new(External::XSEL),
+                Box::new(External::TMUX),
+                Box::new(Osc52),
+            ],
+            ClipboardChoice::X11 => vec![Box::new(Native)],
+            ClipboardChoice::Wayland => vec![Box::new(External::WAYLAND)],
+            ClipboardChoice::Xclip => vec![Box::new(External::XCLIP)],
+            ClipboardChoice::Xsel => vec![Box::new(External::XSEL)],
+            ClipboardChoice::Tmux => vec![Box::new(External::TMUX)],
+            ClipboardChoice::Osc52 => vec![Box::new(Osc52)],
+            ClipboardChoice::File(path) => vec![Box::new(FileClipboard(path.clone()))],
+        }
+    }
+
+    /// Copies `text` with the first backend that works, returning its name
+    pub fn copy(&self, text: &str) -> Result<&'static str> {
+        let auto = *self == ClipboardChoice::Auto;
+        let mut failures = Vec::new();
+
+        for backend in self.backends() {
+            // An explicitly chosen backend is always attempted so its real error is reported
+            if auto && !backend.is_available() {
+                debug!("Clipboard backend {} is not available", backend.name());
+                failures.push(format!("{}: not available", backend.name()));
+                continue;
+            }
+
+            match backend.copy(text) {
+                Ok(()) => {
+                    info!("Copied with clipboard backend {}", backend.name());
+                    return Ok(backend.name());
+                }
+                Err(err) => {
+                    debug!("Clipboard backend {} failed: {:#}", backend.name(), err);
+                    failures.push(format!("{}: {:#}", backend.name(), err));
+                }
+            }
+        }
+
+        bail!(
+            "Failed to copy prompt to clipboard ({}). Use --stdout or -F to write it elsewhere",
+            failures.join("; ")
+        )
+    }
+}
+
+/// Where the finished prompt is written
+#[derive(Debug, Clone)]
+pub enum Sink {
+    Stdout,
+    File(PathBuf),
+    Clipboard(ClipboardChoice),
+}
+
+impl Sink {
+    pub fn write(&self, prompt: &str) -> Result<()> {
+        match self {
+            Sink::Stdout => {
+                info!("Writing prompt to stdout");
+                let mut stdout = io::stdout().lock();
+                match stdout
+                    .write_all(prompt.as_bytes())
+                    .and_then(|_| stdout.flush())
+                {
+                    // The reader closing the pipe early (e.g. `| head`) is not an error
+                    Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                        Err(err).context("Failed to write prompt to stdout")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Sink::File(path) => {
+                info!("Saving to file: {}", path.display());
+                fs::write(path, prompt).context("Failed to write output file")?;
+                info!("Successfully saved prompt to {}", path.display());
+                Ok(())
+            }
+            Sink::Clipboard(choice) => {
+                info!("Copying prompt to clipboard...");
+                choice.copy(prompt)?;
+                info!("Prompt copied to clipboard successfully!");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipboard_choice_round_trips() {
+        for name in [
+            "auto",
+            "x11",
+            "wayland",
+            "xclip",
+            "xsel",
+            "tmux",
+            "osc52",
+            "file:/tmp/prompt.txt",
+        ] {
+            let choice: ClipboardChoice = name.parse().unwrap();
+            assert_eq!(choice.to_string(), name);
+        }
+        assert_eq!("native".parse(), Ok(ClipboardChoice::X11));
+        assert_eq!("wl-copy".parse(), Ok(ClipboardChoice::Wayland));
+        assert!("pbcopy".parse::<ClipboardChoice>().is_err());
+    }
+
+    /// A stand-in for xclip that saves its input and leaves a process holding its output open
+    #[cfg(unix)]
+    fn fake_program(dir: &std::path::Path, script: &str) -> &'static str {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("fake-xclip");
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        Box::leak(path.display().to_string().into_boxed_str())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_backend_does_not_wait_for_forked_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("saved.txt");
+        let script = format!("#!/bin/sh\ncat > '{}'\nsleep 4 &\n", saved.display());
+        let backend = External {
+            name: "fake",
+            program: fake_program(dir.path(), &script),
+            args: &[],
+            requires_env: "PATH",
+        };
+
+        let started = std::time::Instant::now();
+        backend.copy("prompt text").unwrap();
+        assert!(started.elapsed().as_secs() < 3, "{:?}", started.elapsed());
+        assert_eq!(fs::read_to_string(&saved).unwrap(), "prompt text");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_backend_reports_exit_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = External {
+            name: "fake",
+            program: fake_program(dir.path(), "#!/bin/sh\ncat > /dev/null\nexit 3\n"),
+            args: &[],
+            requires_env: "PATH",
+        };
+        let err = backend.copy("prompt text").unwrap_err().to_string();
+        assert!(err.contains("exited with"), "{}", err);
+    }
+
+    #[test]
+    fn file_clipboard_writes_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clipboard.txt");
+        let choice = ClipboardChoice::File(path.clone());
+
+        assert_eq!(choice.copy("prompt text").unwrap(), "file");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "prompt text");
+    }
+
+    #[test]
+    fn file_clipboard_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let choice = ClipboardChoice::File(dir.path().join("missing").join("clipboard.txt"));
+
+        let err = choice.copy("prompt text").unwrap_err().to_string();
+        assert!(err.contains("file: Failed to write"), "{}", err);
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+use syn::{Block, ImplItem, Item, TraitItem, TraitItemFn};
+
+/// What a function body is replaced with
+const ELIDED_BODY: &str = "{ ... }";
+
+/// The byte range of a block, braces included
+fn block_range(block: &Block) -> Range<usize> {
+    block.brace_token.span.join().byte_range()
+}
+
+/// Records the byte range of each function body, descending into impls, traits and inline modules
+fn collect_bodies(items: &[Item], bodies: &mut Vec<Range<usize>>) {
+    for item in items {
+        match item {
+            Item::Fn(item) => bodies.push(block_range(&item.block)),
+            Item::Impl(item) => {
+                for item in &item.items {
+                    if let ImplItem::Fn(item) = item {
+                        bodies.push(block_range(&item.block));
+                    }
+                }
+            }
+            Item::Trait(item) => {
+                for item in &item.items {
+                    if let TraitItem::Fn(TraitItemFn {
+                        default: Some(block),
+                        ..
+                    }) = item
+                    {
+                        bodies.push(block_range(block));
+                    }
+                }
+            }
+            Item::Mod(item) => {
+                if let Some((_, ref items)) = item.content {
+                    collect_bodies(items, bodies);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reduces a Rust source file to its items' signatures, replacing function bodies with `{ ... }`
+///
+/// Structs, enums, traits, impl headers, attributes and doc comments are kept as written.
+pub fn skeleton(content: &str) -> Result<String> {
+    // syn skips a byte order mark and shebang line, so parse past them to keep offsets aligned
+    let mut start = if content.starts_with('\u{feff}') {
+        3
+    } else {
+        0
+    };
+    if content[start..].starts_with("#!") && !content[start..].starts_with("#![") {
+        start += content[start..].find('\n').unwrap_or(content.len() - start);
+    }
+
+    // Parsed spans are kept per thread until released, so turn the parse into plain
+    // byte ranges or an error message before releasing them on either path
+    let bodies = syn::parse_str::<syn::File>(&content[start..])
+        .map(|file| {
+            let mut bodies = Vec::new();
+            collect_bodies(&file.items, &mut bodies);
+            bodies
+        })
+        .map_err(|err| anyhow!("{}", err));
+    proc_macro2::extra::invalidate_current_thread_spans();
+    let bodies = bodies?;
+
+    let mut out = String::with_capacity(content.len() / 2);
+    let mut last = 0;
+    for body in bodies {
+        out.push_str(&content[last..start + body.start]);
+        out.push_str(ELIDED_BODY);
+        last = start + body.end;
+    }
+    out.push_str(&content[last..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elides_function_bodies() {
+        let content = "/// Adds\npub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\nstruct S {\n    x: u8,\n}\n";
+        assert_eq!(
+            skeleton(content).unwrap(),
+            "/// Adds\npub fn add(a: i32, b: i32) -> i32 { ... }\n\nstruct S {\n    x: u8,\n}\n"
+        );
+    }
+
+    #[test]
+    fn elides_impl_and_trait_default_methods() {
+        let content = "impl S {\n    fn new() -> Self {\n        S\n    }\n}\n\
+                       trait T {\n    fn required(&self);\n    fn provided(&self) {\n        self.required()\n    }\n}\n";
+        assert_eq!(
+            skeleton(content).unwrap(),
+            "impl S {\n    fn new() -> Self { ... }\n}\n\
+             trait T {\n    fn required(&self);\n    fn provided(&self) { ... }\n}\n"
+        );
+    }
+
+    #[test]
+    fn descends_into_inline_modules() {
+        let content = "mod inner {\n    fn f() {\n        g();\n    }\n}\nmod outer;\n";
+        assert_eq!(
+            skeleton(content).unwrap(),
+            "mod inner {\n    fn f() { ... }\n}\nmod outer;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_offsets_after_bom_and_shebang() {
+        let content = "\u{feff}#!/usr/bin/env run-cargo-script\nfn main() {\n    run();\n}\n";
+        assert_eq!(
+            skeleton(content).unwrap(),
+            "\u{feff}#!/usr/bin/env run-cargo-script\nfn main() { ... }\n"
+        );
+
+        let content = "#![allow(dead_code)]\nfn f() {\n    1;\n}\n";
+        assert_eq!(
+            skeleton(content).unwrap(),
+            "#![allow(dead_code)]\nfn f() { ... }\n"
+        );
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert!(skeleton("fn broken( {").is_err());
+        // Spans from the failed parse are released, so later files still parse
+        assert_eq!(skeleton("fn f() { 1; }").unwrap(), "fn f() { ... }");
+    }
+}
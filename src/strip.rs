@@ -0,0 +1,589 @@
+use std::path::Path;
+
+use crate::format::language_for;
+
+/// How comments and string literals are written in a language
+#[derive(Debug, Default)]
+struct Syntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    /// Block comments may contain other block comments, as in Rust and Swift
+    nested: bool,
+    /// Quotes whose strings honor backslash escapes
+    quotes: &'static [u8],
+    /// Quotes whose strings run to the next identical quote, like Go raw strings
+    raw_quotes: &'static [u8],
+    /// Tripled quotes open multi-line strings, as in Python and TOML
+    triple_quotes: bool,
+    /// `#` only starts a comment at the start of a word, as in shell and YAML
+    word_comments: bool,
+    /// Quotes only open a string at the start of a value, as in YAML
+    value_quotes: bool,
+    /// A `'` inside a number separates digits, as in C++14 and C23
+    digit_separators: bool,
+    /// `/` opens a regular expression literal where an expression can start, as in JavaScript
+    regex_literals: bool,
+    /// Rust raw strings (`r#"..."#`) and lifetimes that look like char literals
+    rust: bool,
+}
+
+const C_LIKE: Syntax = Syntax {
+    line: &["//"],
+    block: Some(("/*", "*/")),
+    nested: false,
+    quotes: b"\"'",
+    raw_quotes: b"",
+    triple_quotes: false,
+    word_comments: false,
+    value_quotes: false,
+    digit_separators: false,
+    regex_literals: false,
+    rust: false,
+};
+
+/// Looks up the comment syntax for a file, or `None` for unknown languages
+fn syntax_for(path: &Path) -> Option<Syntax> {
+    let syntax = match language_for(path) {
+        "rust" => Syntax {
+            nested: true,
+            quotes: b"\"",
+            rust: true,
+            ..C_LIKE
+        },
+        "python" => Syntax {
+            line: &["#"],
+            quotes: b"\"'",
+            triple_quotes: true,
+            ..Syntax::default()
+        },
+        "javascript" | "jsx" | "typescript" | "tsx" => Syntax {
+            quotes: b"\"'`",
+            regex_literals: true,
+            ..C_LIKE
+        },
+        "go" => Syntax {
+            raw_quotes: b"`",
+            ..C_LIKE
+        },
+        "c" | "cpp" => Syntax {
+            digit_separators: true,
+            ..C_LIKE
+        },
+        "csharp" | "java" | "dart" => C_LIKE,
+        "kotlin" | "scala" | "swift" => Syntax {
+            nested: true,
+            triple_quotes: true,
+            ..C_LIKE
+        },
+        "css" => Syntax {
+            line: &[],
+            ..C_LIKE
+        },
+        "scss" => C_LIKE,
+        "bash" => Syntax {
+            line: &["#"],
+            quotes: b"\"",
+            raw_quotes: b"'",
+            word_comments: true,
+            ..Syntax::default()
+        },
+        "toml" => Syntax {
+            line: &["#"],
+            quotes: b"\"",
+            raw_quotes: b"'",
+            triple_quotes: true,
+            ..Syntax::default()
+        },
+        "yaml" => Syntax {
+            line: &["#"],
+            quotes: b"\"",
+            raw_quotes: b"'",
+            word_comments: true,
+            value_quotes: true,
+            ..Syntax::default()
+        },
+        "sql" => Syntax {
+            line: &["--"],
+            quotes: b"\"",
+            raw_quotes: b"'",
+            ..C_LIKE
+        },
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// Builds the stripped output a line at a time so comment-only lines can be dropped
+struct Output {
+    out: Vec<u8>,
+    line_start: usize,
+    /// Whether a comment was removed from the current line
+    touched: bool,
+}
+
+impl Output {
+    /// Ends the current line, dropping it if removing a comment left it blank
+    fn end_line(&mut self) {
+        if self.touched {
+            while self.out.len() > self.line_start
+                && matches!(self.out.last(), Some(b' ' | b'\t' | b'\r'))
+            {
+                self.out.pop();
+            }
+            if self.out.len() == self.line_start {
+                self.touched = false;
+                return;
+            }
+        }
+        self.out.push(b'\n');
+        self.line_start = self.out.len();
+        self.touched = false;
+    }
+
+    /// Copies string contents verbatim, tracking where lines start
+    fn copy(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+        if let Some(newline) = bytes.iter().rposition(|&b| b == b'\n') {
+            self.line_start = self.out.len() - (bytes.len() - newline - 1);
+            self.touched = false;
+        }
+    }
+}
+
+/// Finds the end of a string literal opened at `start`, just past its closing quote
+fn string_end(bytes: &[u8], start: usize, quote: &[u8], escapes: bool) -> usize {
+    let mut i = start + quote.len();
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i..].starts_with(quote) {
+            return i + quote.len();
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Finds the end of a Rust raw string such as `r#"..."#` or `br"..."`, if one starts at `i`
+fn rust_raw_string_end(bytes: &[u8], i: usize) -> Option<usize> {
+    if i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_') {
+        return None;
+    }
+    let mut j = i;
+    if bytes.get(j) == Some(&b'b') {
+        j += 1;
+    }
+    if bytes.get(j) != Some(&b'r') {
+        return None;
+    }
+    j += 1;
+    let hashes = bytes[j..].iter().take_while(|&&b| b == b'#').count();
+    j += hashes;
+    if bytes.get(j) != Some(&b'"') {
+        return None;
+    }
+
+    let mut closing = vec![b'"'];
+    closing.resize(hashes + 1, b'#');
+    Some(string_end(bytes, j, &closing, false))
+}
+
+/// Whether `i` starts a YAML value: at the start of a line, or after `: `, `- `, `[`, `{` or `,`
+fn at_value_start(bytes: &[u8], i: usize) -> bool {
+    let before = &bytes[..i];
+    let line = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(before, |newline| &before[newline + 1..]);
+    let trimmed = line.trim_ascii_end();
+    match trimmed.last() {
+        None | Some(b'[' | b'{' | b',') => true,
+        Some(b':' | b'-') => trimmed.len() < line.len(),
+        _ => false,
+    }
+}
+
+/// Whether the `'` at `i` separates digits in a number literal such as `1'000` or `0xff'ff`
+fn is_digit_separator(bytes: &[u8], i: usize) -> bool {
+    let word_start = bytes[..i]
+        .iter()
+        .rposition(|&b| !(b.is_ascii_alphanumeric() || b == b'\'' || b == b'.'))
+        .map_or(0, |pos| pos + 1);
+    i > word_start
+        && bytes[word_start].is_ascii_digit()
+        && bytes[i - 1].is_ascii_hexdigit()
+        && bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+}
+
+/// Finds the end of a JavaScript regex literal opened by the `/` at `i`
+///
+/// Returns `None` when the `/` is more likely division, or when the literal does not
+/// close on the same line, so the rest of the line is left to the comment scan.
+fn regex_literal_end(bytes: &[u8], i: usize) -> Option<usize> {
+    if matches!(bytes.get(i + 1), Some(b'/' | b'*')) {
+        return None;
+    }
+
+    // A regex can only appear where an expression starts: after an operator,
+    // an opening bracket or a keyword such as `return`
+    let before = bytes[..i].trim_ascii_end();
+    let expression_start = match before.last() {
+        None => true,
+        Some(b) if b"(,=:[!&|?{};+-*%<>~^".contains(b) => true,
+        Some(b) if b.is_ascii_alphabetic() => {
+            let word_start = before
+                .iter()
+                .rposition(|b| !(b.is_ascii_alphanumeric() || *b == b'_' || *b == b'$'))
+                .map_or(0, |pos| pos + 1);
+            matches!(
+                &before[word_start..],
+                b"return"
+                    | b"typeof"
+                    | b"instanceof"
+                    | b"in"
+                    | b"of"
+                    | b"new"
+                    | b"delete"
+                    | b"void"
+                    | b"throw"
+                    | b"case"
+                    | b"do"
+                    | b"else"
+                    | b"yield"
+                    | b"await"
+            )
+        }
+        _ => false,
+    };
+    if !expression_start {
+        return None;
+    }
+
+    let mut class = false;
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\n' => return None,
+            b'\\' => j += 1,
+            b'[' => class = true,
+            b']' => class = false,
+            b'/' if !class => {
+                j += 1;
+                // Flags such as `g`, `i` and `u`
+                while bytes.get(j).is_some_and(u8::is_ascii_alphabetic) {
+                    j += 1;
+                }
+                return Some(j);
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+/// Finds the end of a Rust char literal at `i`, or `None` when the quote starts a lifetime
+fn rust_char_end(bytes: &[u8], i: usize) -> Option<usize> {
+    match bytes.get(i + 1) {
+        Some(b'\\') => Some(string_end(bytes, i, b"'", true)),
+        Some(_) => {
+            // A char literal closes after one character, which may be several bytes long
+            let rest = std::str::from_utf8(&bytes[i + 1..]).ok()?;
+            let width = rest.chars().next()?.len_utf8();
+            (bytes.get(i + 1 + width) == Some(&b'\'')).then_some(i + 2 + width)
+        }
+        None => None,
+    }
+}
+
+/// Removes line and block comments, keeping string literals intact
+///
+/// Lines left blank by removing a comment are dropped. Files in languages
+/// without known comment syntax are returned unchanged.
+pub fn strip_comments(path: &Path, content: &str) -> String {
+    let Some(syntax) = syntax_for(path) else {
+        return content.to_string();
+    };
+
+    let bytes = content.as_bytes();
+    let mut output = Output {
+        out: Vec::with_capacity(bytes.len()),
+        line_start: 0,
+        touched: false,
+    };
+    let mut i = 0;
+
+    // Keep a shebang line even though it looks like a comment
+    if syntax.line.contains(&"#") && bytes.starts_with(b"#!") {
+        let end = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(bytes.len());
+        output.copy(&bytes[..end]);
+        i = end;
+    }
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let rest = &bytes[i..];
+
+        if byte == b'\n' {
+            output.end_line();
+            i += 1;
+            continue;
+        }
+
+        // String literals are copied verbatim, comment markers and all
+        if syntax.rust {
+            if let Some(end) = rust_raw_string_end(bytes, i) {
+                output.copy(&bytes[i..end]);
+                i = end;
+                continue;
+            }
+            if byte == b'\'' {
+                let end = rust_char_end(bytes, i).unwrap_or(i + 1);
+                output.copy(&bytes[i..end]);
+                i = end;
+                continue;
+            }
+        }
+        if syntax.digit_separators && byte == b'\'' && is_digit_separator(bytes, i) {
+            output.out.push(byte);
+            i += 1;
+            continue;
+        }
+        if syntax.regex_literals && byte == b'/' {
+            if let Some(end) = regex_literal_end(bytes, i) {
+                output.copy(&bytes[i..end]);
+                i = end;
+                continue;
+            }
+        }
+        let escapes = syntax.quotes.contains(&byte);
+        if (escapes || syntax.raw_quotes.contains(&byte))
+            && (!syntax.value_quotes || at_value_start(bytes, i))
+        {
+            let triple = [byte; 3];
+            let quote: &[u8] = if syntax.triple_quotes && rest.starts_with(&triple) {
+                &triple
+            } else {
+                &triple[..1]
+            };
+            let end = string_end(bytes, i, quote, escapes);
+            output.copy(&bytes[i..end]);
+            i = end;
+            continue;
+        }
+
+        if let Some((open, close)) = syntax
+            .block
+            .filter(|(open, _)| rest.starts_with(open.as_bytes()))
+        {
+            let mut depth = 0;
+            let mut j = i;
+            let mut newline = false;
+            while j < bytes.len() {
+                if bytes[j..].starts_with(open.as_bytes()) && (syntax.nested || depth == 0) {
+                    depth += 1;
+                    j += open.len();
+                } else if bytes[j..].starts_with(close.as_bytes()) {
+                    depth -= 1;
+                    j += close.len();
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    newline |= bytes[j] == b'\n';
+                    j += 1;
+                }
+            }
+
+            // A comment spanning lines still separates the code around it
+            output.touched = true;
+            if newline {
+                output.end_line();
+                output.touched = true;
+            }
+            i = j;
+            continue;
+        }
+
+        let at_word_start = i == 0 || bytes[i - 1].is_ascii_whitespace();
+        if syntax
+            .line
+            .iter()
+            .any(|marker| rest.starts_with(marker.as_bytes()))
+            && (!syntax.word_comments || at_word_start)
+        {
+            output.touched = true;
+            i += rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            continue;
+        }
+
+        output.out.push(byte);
+        i += 1;
+    }
+    if output.touched {
+        output.end_line();
+        // end_line adds a newline the original text did not have
+        if output.out.last() == Some(&b'\n') && !content.ends_with('\n') {
+            output.out.pop();
+        }
+    }
+
+    String::from_utf8(output.out)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+}
+
+/// Trims trailing whitespace and collapses runs of blank lines into one
+pub fn compact(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut blank = false;
+    for line in content.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(name: &str, content: &str) -> String {
+        strip_comments(Path::new(name), content)
+    }
+
+    #[test]
+    fn rust_keeps_markers_in_strings() {
+        let content = "let a = \"// not\"; // gone\nlet b = \"/* not */\";\n";
+        assert_eq!(
+            strip("a.rs", content),
+            "let a = \"// not\";\nlet b = \"/* not */\";\n"
+        );
+    }
+
+    #[test]
+    fn rust_raw_strings_and_lifetimes() {
+        let content = "let s = r#\"a \"// b\"#; // c\nfn f<'a>(x: &'a str) -> char { '/' } // d\n";
+        assert_eq!(
+            strip("a.rs", content),
+            "let s = r#\"a \"// b\"#;\nfn f<'a>(x: &'a str) -> char { '/' }\n"
+        );
+    }
+
+    #[test]
+    fn rust_nested_block_comments() {
+        assert_eq!(strip("a.rs", "a /* one /* two */ still */ b\n"), "a  b\n");
+    }
+
+    #[test]
+    fn drops_comment_only_lines() {
+        let content = "// header\nfn main() {}\n/*\n * doc\n */\nfn f() {}\n";
+        assert_eq!(strip("a.rs", content), "fn main() {}\nfn f() {}\n");
+    }
+
+    #[test]
+    fn python_hash_in_string() {
+        let content = "#!/usr/bin/env python\nx = \"# not\"  # gone\ny = '''\n# kept\n'''\n";
+        assert_eq!(
+            strip("a.py", content),
+            "#!/usr/bin/env python\nx = \"# not\"\ny = '''\n# kept\n'''\n"
+        );
+    }
+
+    #[test]
+    fn javascript_template_literals() {
+        let content = "const a = `// ${b} /* c */`; // gone\n";
+        assert_eq!(strip("a.js", content), "const a = `// ${b} /* c */`;\n");
+    }
+
+    #[test]
+    fn javascript_regex_literals() {
+        let content = "const url = /https?:\\/\\//; // gone\n\
+                       if (/[/*]/.test(s)) return /'/g; // gone\n\
+                       const half = total / 2; // gone\n\
+                       const ratio = a / b / c; // gone\n";
+        assert_eq!(
+            strip("a.ts", content),
+            "const url = /https?:\\/\\//;\n\
+             if (/[/*]/.test(s)) return /'/g;\n\
+             const half = total / 2;\n\
+             const ratio = a / b / c;\n"
+        );
+    }
+
+    #[test]
+    fn go_raw_strings() {
+        let content = "s := `\\` // gone\nt := \"\\\"//\" // gone\n";
+        assert_eq!(strip("a.go", content), "s := `\\`\nt := \"\\\"//\"\n");
+    }
+
+    #[test]
+    fn shell_hash_only_at_word_start() {
+        let content = "echo a#b $# # gone\necho '# kept' \"# kept\"\n";
+        assert_eq!(
+            strip("a.sh", content),
+            "echo a#b $#\necho '# kept' \"# kept\"\n"
+        );
+    }
+
+    #[test]
+    fn yaml_quotes_only_open_values() {
+        let content = "name: don't panic # c\nkey: v # c\nother: 'quoted # not comment'\n\
+                       list: [a, 'b # c']\n- \"x # y\"\n";
+        assert_eq!(
+            strip("a.yaml", content),
+            "name: don't panic\nkey: v\nother: 'quoted # not comment'\n\
+             list: [a, 'b # c']\n- \"x # y\"\n"
+        );
+    }
+
+    #[test]
+    fn toml_strings() {
+        let content = "a = \"# not\" # gone\nb = '''\n# kept\n'''\n";
+        assert_eq!(
+            strip("a.toml", content),
+            "a = \"# not\"\nb = '''\n# kept\n'''\n"
+        );
+    }
+
+    #[test]
+    fn c_char_and_sql_strings() {
+        assert_eq!(strip("a.c", "char c = '\"'; // x\n"), "char c = '\"';\n");
+        assert_eq!(
+            strip("a.sql", "SELECT '--' -- gone\nFROM t;\n"),
+            "SELECT '--'\nFROM t;\n"
+        );
+    }
+
+    #[test]
+    fn cpp_digit_separators() {
+        let content = "int n = 1'000'000; // gone\nauto m = 0xff'ff; char c = 'a'; // gone\n\
+                       auto u = u8'x'; // gone\n";
+        assert_eq!(
+            strip("a.cpp", content),
+            "int n = 1'000'000;\nauto m = 0xff'ff; char c = 'a';\nauto u = u8'x';\n"
+        );
+    }
+
+    #[test]
+    fn unknown_languages_unchanged() {
+        let content = "# not a comment\n";
+        assert_eq!(strip("a.txt", content), content);
+    }
+
+    #[test]
+    fn compact_collapses_blank_runs() {
+        assert_eq!(compact("a  \n\n\n\nb\t\n\n"), "a\n\nb\n");
+    }
+}
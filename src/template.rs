@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use std::{fs, path::PathBuf};
+
+use crate::format::{language_for, Format, OutputFile, DEFAULT_TASK};
+
+/// Values shared by every placeholder in the prompt template
+pub struct PromptContext<'a> {
+    pub tree: &'a str,
+    pub task: Option<&'a str>,
+    pub git_branch: &'a str,
+    pub token_count: usize,
+}
+
+/// Directory holding named templates, e.g. `~/.config/rustprelude/templates`
+pub fn templates_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustprelude").join("templates"))
+}
+
+/// Loads a template from a file path, or by name from the templates directory
+pub fn load(spec: &str) -> Result<String> {
+    let path = PathBuf::from(spec);
+    if path.is_file() {
+        return fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read template {}", path.display()));
+    }
+
+    if let Some(dir) = templates_dir() {
+        let name = spec.strip_suffix(".tmpl").unwrap_or(spec);
+        let named = dir.join(format!("{}.tmpl", name));
+        if named.is_file() {
+            return fs::read_to_string(&named)
+                .with_context(|| format!("Failed to read template {}", named.display()));
+        }
+    }
+
+    bail!(
+        "Template '{}' is neither a file nor a named template in {}",
+        spec,
+        templates_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|| "the config directory".to_string())
+    )
+}
+
+/// Replaces `{name}` placeholders in a single pass
+///
+/// Unknown placeholders are left untouched so braces in the template survive,
+/// and `{{` / `}}` produce literal braces.
+fn substitute(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        if rest.starts_with('{') {
+            if let Some(end) = rest.find('}') {
+                let name = &rest[1..end];
+                let is_placeholder =
+                    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if let Some(value) = lookup(name).filter(|_| is_placeholder) {
+                    out.push_str(&value);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        out.push_str(&rest[..1]);
+        rest = &rest[1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Renders one file through a per-file template
+fn render_file_template(template: &str, file: &OutputFile) -> String {
+    substitute(template, |name| match name {
+        "path" => Some(file.path.display().to_string()),
+        "content" => Some(file.content.clone()),
+        "lang" => Some(language_for(&file.path).to_string()),
+        "size" => Some(file.size.to_string()),
+        "lines" => Some(file.content.lines().count().to_string()),
+        "diff" => Some(file.diff.clone().unwrap_or_default()),
+        _ => None,
+    })
+}
+
+/// Renders one file through the per-file template, or the format's layout without one
+pub fn render_file(format: Format, file_template: Option<&str>, file: &OutputFile) -> String {
+    match file_template {
+        Some(template) => render_file_template(template, file),
+        None => format.render_file(file),
+    }
+}
+
+/// Renders the full prompt, falling back to the format's built-in templates
+pub fn render_prompt(
+    format: Format,
+    prompt_template: Option<&str>,
+    file_template: Option<&str>,
+    context: &PromptContext,
+    files: &[OutputFile],
+) -> Result<String> {
+    let Some(prompt_template) = prompt_template.or(format.default_template()) else {
+        return Format::render_json(context.tree, context.task, files);
+    };
+
+    let rendered_files: String = files
+        .iter()
+        .map(|file| render_file(format, file_template, file))
+        .collect();
+
+    Ok(substitute(prompt_template, |name| match name {
+        "tree" => Some(context.tree.to_string()),
+        "files" => Some(rendered_files.clone()),
+        "file_count" => Some(files.len().to_string()),
+        "token_count" => Some(context.token_count.to_string()),
+        "git_branch" => Some(context.git_branch.to_string()),
+        "task" => Some(context.task.unwrap_or(DEFAULT_TASK).to_string()),
+        _ => None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "name" => Some("world".to_string()),
+            "braces" => Some("{name}".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitutes_known_placeholders() {
+        assert_eq!(substitute("Hello, {name}!", lookup), "Hello, world!");
+        assert_eq!(substitute("{name}{name}", lookup), "worldworld");
+    }
+
+    #[test]
+    fn leaves_unknown_placeholders_and_code_alone() {
+        assert_eq!(substitute("{unknown} {name", lookup), "{unknown} {name");
+        assert_eq!(
+            substitute("fn f() { x }\n{ name }", lookup),
+            "fn f() { x }\n{ name }"
+        );
+        assert_eq!(substitute("{}", lookup), "{}");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(substitute("{{name}} {name}", lookup), "{name} world");
+        assert_eq!(substitute("}} {{", lookup), "} {");
+    }
+
+    #[test]
+    fn values_are_not_substituted_again() {
+        assert_eq!(substitute("{braces}", lookup), "{name}");
+    }
+
+    #[test]
+    fn renders_file_template() {
+        let mut file = OutputFile::new(PathBuf::from("src/main.rs"), "a\nb\n".to_string());
+        file.diff = Some("+b".to_string());
+
+        assert_eq!(
+            render_file_template(
+                "{path} {lang} {size} {lines} {diff} {other}\n{content}",
+                &file
+            ),
+            "src/main.rs rust 4 2 +b {other}\na\nb\n"
+        );
+
+        file.diff = None;
+        assert_eq!(render_file_template("[{diff}]", &file), "[]");
+    }
+
+    #[test]
+    fn loads_template_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("custom.tmpl");
+        fs::write(&path, "{tree}").unwrap();
+
+        assert_eq!(load(path.to_str().unwrap()).unwrap(), "{tree}");
+    }
+
+    #[test]
+    fn missing_template_error() {
+        let err = load("no-such-template-anywhere").unwrap_err().to_string();
+
+        assert!(
+            err.starts_with(
+                "Template 'no-such-template-anywhere' is neither a file nor a named template in "
+            ),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use clap::ValueEnum;
+use globset::GlobSet;
+use log::info;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, path::PathBuf};
+use tiktoken_rs::CoreBPE;
+
+use crate::matches_globset;
+
+/// Marker appended to file contents cut short by the token budget
+const TRUNCATION_MARKER: &str = "\n[... truncated to fit token budget ...]\n";
+
+/// Estimates how many tokens a piece of text will use
+pub trait TokenEstimator: Send + Sync {
+    /// Counts the tokens in `text`
+    fn count(&self, text: &str) -> usize;
+
+    /// Cuts `text` down to at most `max_tokens` tokens
+    fn truncate(&self, text: &str, max_tokens: usize) -> String;
+}
+
+/// Cheap approximation of roughly four characters per token
+pub struct Heuristic;
+
+impl TokenEstimator for Heuristic {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        text.chars().take(max_tokens * 4).collect()
+    }
+}
+
+/// Exact counts using a byte pair encoding table bundled in the binary
+pub struct Bpe(&'static CoreBPE);
+
+impl TokenEstimator for Bpe {
+    fn count(&self, text: &str) -> usize {
+        self.0.encode_ordinary(text).len()
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let mut tokens = self.0.encode_ordinary(text);
+        tokens.truncate(max_tokens);
+
+        // A cut can land inside a multi-byte character, so back off until it decodes
+        while !tokens.is_empty() {
+            if let Ok(decoded) = self.0.decode(tokens.clone()) {
+                return decoded;
+            }
+            tokens.pop();
+        }
+        String::new()
+    }
+}
+
+/// The tokenizer used to estimate token counts
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tokenizer {
+    /// Characters divided by four
+    #[default]
+    Heuristic,
+    /// OpenAI cl100k_base encoding (GPT-4, GPT-3.5)
+    Cl100k,
+    /// OpenAI o200k_base encoding (GPT-4o)
+    O200k,
+}
+
+impl Tokenizer {
+    pub fn estimator(self) -> Box<dyn TokenEstimator> {
+        match self {
+            Tokenizer::Heuristic => Box::new(Heuristic),
+            Tokenizer::Cl100k => Box::new(Bpe(tiktoken_rs::cl100k_base_singleton())),
+            Tokenizer::O200k => Box::new(Bpe(tiktoken_rs::o200k_base_singleton())),
+        }
+    }
+}
+
+/// How files are cut when the token budget is exceeded
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetPolicy {
+    /// Drop the largest files first
+    #[default]
+    DropLargest,
+    /// Drop files from the end of the sorted file list
+    DropLast,
+    /// Keep files in order and truncate the first one that overflows
+    Truncate,
+}
+
+/// A file's contents together with its estimated token count
+#[derive(Debug, Clone)]
+pub struct CountedFile {
+    pub path: PathBuf,
+    pub content: String,
+    pub tokens: usize,
+}
+
+/// The files kept and dropped when enforcing a token budget
+pub struct BudgetOutcome {
+    pub kept: Vec<CountedFile>,
+    pub dropped: Vec<PathBuf>,
+    pub truncated: Vec<PathBuf>,
+}
+
+/// Counts tokens for every file and logs a per-file and total report
+pub fn count_files(
+    files: Vec<(PathBuf, String)>,
+    estimator: &dyn TokenEstimator,
+) -> Vec<CountedFile> {
+    let counted: Vec<CountedFile> = files
+        .into_par_iter()
+        .map(|(path, content)| {
+            let tokens = estimator.count(&content);
+            CountedFile {
+                path,
+                content,
+                tokens,
+            }
+        })
+        .collect();
+
+    info!("Token counts:");
+    for file in &counted {
+        info!("{:>8}  {}", file.tokens, file.path.display());
+    }
+    info!(
+        "{:>8}  total",
+        counted.iter().map(|file| file.tokens).sum::<usize>()
+    );
+
+    counted
+}
+
+/// Drops or truncates files until their combined token count fits in `max_tokens`
+///
+/// Files matching `priority` are only cut once every other file has been cut.
+pub fn enforce_budget(
+    files: Vec<CountedFile>,
+    max_tokens: usize,
+    policy: BudgetPolicy,
+    priority: Option<&GlobSet>,
+    estimator: &dyn TokenEstimator,
+) -> BudgetOutcome {
+    let is_priority =
+        |file: &CountedFile| priority.is_some_and(|set| matches_globset(set, &file.path));
+
+    // Order in which files are cut, first cut first
+    let mut cut_order: Vec<usize> = (0..files.len()).collect();
+    cut_order.sort_by_key(|&i| {
+        let rank = match policy {
+            BudgetPolicy::DropLargest => files[i].tokens,
+            BudgetPolicy::DropLast | BudgetPolicy::Truncate => i,
+        };
+        (is_priority(&files[i]), Reverse(rank))
+    });
+
+    let mut total: usize = files.iter().map(|file| file.tokens).sum();
+    let mut dropped = vec![false; files.len()];
+    let mut truncated_to: Vec<Option<usize>> = vec![None; files.len()];
+
+    for &index in &cut_order {
+        if total <= max_tokens {
+            break;
+        }
+
+        let tokens = files[index].tokens;
+        let overflow = total - max_tokens;
+        if matches!(policy, BudgetPolicy::Truncate) && tokens > overflow {
+            truncated_to[index] = Some(tokens - overflow);
+            total -= overflow;
+        } else {
+            dropped[index] = true;
+            total -= tokens;
+        }
+    }
+
+    let mut outcome = BudgetOutcome {
+        kept: Vec::new(),
+        dropped: Vec::new(),
+        truncated: Vec::new(),
+    };
+    for (index, mut file) in files.into_iter().enumerate() {
+        if dropped[index] {
+            outcome.dropped.push(file.path);
+            continue;
+        }
+        if let Some(limit) = truncated_to[index] {
+            let marker_tokens = estimator.count(TRUNCATION_MARKER);
+            let mut content =
+                estimator.truncate(&file.content, limit.saturating_sub(marker_tokens));
+            content.push_str(TRUNCATION_MARKER);
+            file.tokens = estimator.count(&content);
+            file.content = content;
+            outcome.truncated.push(file.path.clone());
+        }
+        outcome.kept.push(file);
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_globset;
+
+    /// Files whose content is four characters per heuristic token
+    fn files(tokens: &[(&str, usize)]) -> Vec<CountedFile> {
+        tokens
+            .iter()
+            .map(|&(path, tokens)| CountedFile {
+                path: PathBuf::from(path),
+                content: "x".repeat(tokens * 4),
+                tokens,
+            })
+            .collect()
+    }
+
+    fn paths(paths: &[PathBuf]) -> Vec<&str> {
+        paths.iter().map(|path| path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn heuristic_counts_four_chars_per_token() {
+        assert_eq!(Heuristic.count(""), 0);
+        assert_eq!(Heuristic.count("abcde"), 2);
+        assert_eq!(Heuristic.truncate("abcdefghij", 2), "abcdefgh");
+    }
+
+    #[test]
+    fn fits_without_cutting() {
+        let outcome = enforce_budget(
+            files(&[("a", 5), ("b", 5)]),
+            10,
+            BudgetPolicy::DropLargest,
+            None,
+            &Heuristic,
+        );
+        assert_eq!(outcome.kept.len(), 2);
+        assert!(outcome.dropped.is_empty() && outcome.truncated.is_empty());
+    }
+
+    #[test]
+    fn drop_largest_cuts_biggest_file_first() {
+        let outcome = enforce_budget(
+            files(&[("a", 5), ("b", 20), ("c", 10)]),
+            20,
+            BudgetPolicy::DropLargest,
+            None,
+            &Heuristic,
+        );
+        assert_eq!(paths(&outcome.dropped), ["b"]);
+        assert_eq!(outcome.kept.len(), 2);
+    }
+
+    #[test]
+    fn drop_last_cuts_from_the_end() {
+        let outcome = enforce_budget(
+            files(&[("a", 20), ("b", 5), ("c", 5)]),
+            22,
+            BudgetPolicy::DropLast,
+            None,
+            &Heuristic,
+        );
+        assert_eq!(paths(&outcome.dropped), ["b", "c"]);
+    }
+
+    #[test]
+    fn priority_files_are_cut_last() {
+        let priority = build_globset(&["b".to_string()], false).unwrap();
+        let outcome = enforce_budget(
+            files(&[("a", 5), ("b", 20), ("c", 10)]),
+            20,
+            BudgetPolicy::DropLargest,
+            priority.as_ref(),
+            &Heuristic,
+        );
+        assert_eq!(paths(&outcome.dropped), ["a", "c"]);
+        assert_eq!(paths(&[outcome.kept[0].path.clone()]), ["b"]);
+    }
+
+    #[test]
+    fn truncate_cuts_the_overflowing_file() {
+        let outcome = enforce_budget(
+            files(&[("a", 10), ("b", 10), ("c", 30)]),
+            40,
+            BudgetPolicy::Truncate,
+            None,
+            &Heuristic,
+        );
+        assert!(outcome.dropped.is_empty());
+        assert_eq!(paths(&outcome.truncated), ["c"]);
+
+        let truncated = &outcome.kept[2];
+        assert!(truncated.content.ends_with(TRUNCATION_MARKER));
+        assert!(truncated.tokens <= 20);
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// A file shown in the tree along with its optional metadata
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub path: PathBuf,
+    pub size: Option<u64>,
+    pub lines: Option<usize>,
+    pub note: Option<String>,
+}
+
+/// Controls the connectors and metadata used when rendering the tree
+#[derive(Debug, Clone, Default)]
+pub struct TreeStyle {
+    pub ascii: bool,
+    pub sizes: bool,
+    pub line_counts: bool,
+}
+
+impl TreeStyle {
+    fn branch(&self) -> &'static str {
+        if self.ascii {
+            "|-- "
+        } else {
+            "├── "
+        }
+    }
+
+    fn last_branch(&self) -> &'static str {
+        if self.ascii {
+            "`-- "
+        } else {
+            "└── "
+        }
+    }
+
+    fn pipe(&self) -> &'static str {
+        if self.ascii {
+            "|   "
+        } else {
+            "│   "
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node<'a> {
+    children: BTreeMap<String, Node<'a>>,
+    entry: Option<&'a TreeEntry>,
+}
+
+/// Formats a byte count as a short human readable size
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    if value < 10.0 {
+        format!("{:.1} {}", value, unit)
+    } else {
+        format!("{:.0} {}", value, unit)
+    }
+}
+
+fn describe(entry: &TreeEntry, style: &TreeStyle) -> String {
+    let mut details = Vec::new();
+    if style.sizes {
+        if let Some(size) = entry.size {
+            details.push(format_size(size));
+        }
+    }
+    if style.line_counts {
+        if let Some(lines) = entry.lines {
+            details.push(format!("{} lines", lines));
+        }
+    }
+
+    let mut description = String::new();
+    if !details.is_empty() {
+        description.push_str(&format!(" ({})", details.join(", ")));
+    }
+    if let Some(ref note) = entry.note {
+        description.push_str(&format!(" [{}]", note));
+    }
+    description
+}
+
+fn render(
+    node: &Node,
+    prefix: &str,
+    style: &TreeStyle,
+    out: &mut String,
+    counts: &mut (usize, usize),
+) {
+    let last_index = node.children.len().saturating_sub(1);
+    for (index, (name, child)) in node.children.iter().enumerate() {
+        let is_last = index == last_index;
+        let connector = if is_last {
+            style.last_branch()
+        } else {
+            style.branch()
+        };
+
+        out.push_str(prefix);
+        out.push_str(connector);
+        out.push_str(name);
+
+        if let Some(entry) = child.entry {
+            counts.1 += 1;
+            out.push_str(&describe(entry, style));
+            out.push('\n');
+        } else {
+            counts.0 += 1;
+            out.push('\n');
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { style.pipe() });
+            render(child, &child_prefix, style, out, counts);
+        }
+    }
+}
+
+/// Renders the entries as a nested tree in the style of `tree(1)`
+pub fn build_tree(entries: &[TreeEntry], style: &TreeStyle) -> String {
+    let mut root = Node::default();
+    for entry in entries {
+        let mut node = &mut root;
+        for component in entry.path.iter() {
+            node = node
+                .children
+                .entry(component.to_string_lossy().into_owned())
+                .or_default();
+        }
+        node.entry = Some(entry);
+    }
+
+    let mut tree = String::from(".\n");
+    let mut counts = (0, 0);
+    render(&root, "", style, &mut tree, &mut counts);
+
+    let (dirs, files) = counts;
+    tree.push_str(&format!(
+        "\n{} {}, {} {}\n",
+        dirs,
+        if dirs == 1 {
+            "directory"
+        } else {
+            "directories"
+        },
+        files,
+        if files == 1 { "file" } else { "files" }
+    ));
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: u64, lines: usize) -> TreeEntry {
+        TreeEntry {
+            path: PathBuf::from(path),
+            size: Some(size),
+            lines: Some(lines),
+            note: None,
+        }
+    }
+
+    fn entries() -> Vec<TreeEntry> {
+        vec![
+            entry("src/main.rs", 2048, 80),
+            entry("src/util/mod.rs", 10, 1),
+            entry("Cargo.toml", 300, 12),
+        ]
+    }
+
+    fn lines(lines: &[&str]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn unicode_connectors() {
+        assert_eq!(
+            build_tree(&entries(), &TreeStyle::default()),
+            lines(&[
+                ".",
+                "├── Cargo.toml",
+                "└── src",
+                "    ├── main.rs",
+                "    └── util",
+                "        └── mod.rs",
+                "",
+                "2 directories, 3 files",
+            ])
+        );
+    }
+
+    #[test]
+    fn ascii_connectors() {
+        let mut entries = entries();
+        entries.push(entry("src/lib.rs", 1, 1));
+        entries.push(entry("tests/it.rs", 1, 1));
+        let style = TreeStyle {
+            ascii: true,
+            ..TreeStyle::default()
+        };
+
+        assert_eq!(
+            build_tree(&entries, &style),
+            lines(&[
+                ".",
+                "|-- Cargo.toml",
+                "|-- src",
+                "|   |-- lib.rs",
+                "|   |-- main.rs",
+                "|   `-- util",
+                "|       `-- mod.rs",
+                "`-- tests",
+                "    `-- it.rs",
+                "",
+                "3 directories, 5 files",
+            ])
+        );
+    }
+
+    #[test]
+    fn sizes_line_counts_and_notes() {
+        let mut entries = entries();
+        entries[1].note = Some("binary, 10 B".to_string());
+        let style = TreeStyle {
+            sizes: true,
+            line_counts: true,
+            ..TreeStyle::default()
+        };
+
+        assert_eq!(
+            build_tree(&entries, &style),
+            lines(&[
+                ".",
+                "├── Cargo.toml (300 B, 12 lines)",
+                "└── src",
+                "    ├── main.rs (2.0 KB, 80 lines)",
+                "    └── util",
+                "        └── mod.rs (10 B, 1 lines) [binary, 10 B]",
+                "",
+                "2 directories, 3 files",
+            ])
+        );
+    }
+
+    #[test]
+    fn summary_uses_singular_counts() {
+        let tree = build_tree(&[entry("src/a.rs", 1, 1)], &TreeStyle::default());
+        assert!(tree.ends_with("\n1 directory, 1 file\n"));
+
+        let tree = build_tree(&[], &TreeStyle::default());
+        assert_eq!(tree, ".\n\n0 directories, 0 files\n");
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(20 * 1024 * 1024), "20 MB");
+    }
+}